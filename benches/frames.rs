@@ -0,0 +1,33 @@
+extern crate nes;
+
+use nes::{bus::MemoryBus, cpu::CPU, rom::Rom};
+use std::{env, time::Instant};
+
+const FRAMES: u64 = 600;
+
+// Emulated frames per second on a ROM (nestest by default): cargo bench --bench frames [rom]
+fn main() {
+    // cargo passes --bench along
+    let path = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "assets/nestest.nes".to_owned());
+    let rom = Rom::from_path(path.clone()).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    let mut cpu = CPU::new(MemoryBus::new(rom));
+
+    let start = Instant::now();
+    let mut cycles = 0;
+    for _ in 0..FRAMES {
+        cycles += cpu.run_frame();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "{}: {} frames in {:.3}s, {:.1} frames/s ({:.2} MHz)",
+        path,
+        FRAMES,
+        elapsed,
+        FRAMES as f64 / elapsed,
+        cycles as f64 / elapsed / 1e6
+    );
+}
@@ -1,23 +1,29 @@
 use codegen::Scope;
 use serde_json::{Result, Value};
 use std::collections::HashMap;
+use std::env;
 use std::fs::{read_to_string, write};
-use std::u8;
+use std::path::Path;
 
-fn main() -> Result<()> {
-    let file = read_to_string("ops_codes.json").unwrap();
-    let value: Value = serde_json::from_str(&file)?;
+const OPCODE_FILES: [&str; 2] = ["ops_codes.json", "ops_codes_65c02.json"];
 
-    let mut scope = Scope::new();
-    scope.import("super", "Instruction");
-    scope.import("super", "InstructionType");
-    scope.import("super", "MemoryAdressingMode");
+#[derive(Clone)]
+struct OpCode {
+    mnemonic: String,
+    op_code: u8,
+    bytes: String,
+    cycles: i64,
+    mode: String,
+    plus_cycle: bool,
+}
 
-    scope.raw("pub const INSTRUCTION_SET: [Instruction; 255] = [");
+fn load_instructions(path: &str) -> Result<HashMap<u8, OpCode>> {
+    let file = read_to_string(path).unwrap();
+    let value: Value = serde_json::from_str(&file)?;
 
     let mut instructions = HashMap::new();
     for item in value.as_array().unwrap() {
-        let mnemonic = item["name"].as_str().unwrap();
+        let mnemonic = item["name"].as_str().unwrap().to_uppercase();
 
         let op_code = item["opcode"].as_str().unwrap().to_owned().replace("$", "");
         let op_code = u8::from_str_radix(&op_code, 16).unwrap();
@@ -25,50 +31,151 @@ fn main() -> Result<()> {
         let bytes = item["bytes"].as_str().unwrap().to_owned();
         let cycles = item["cycles"].as_i64().unwrap();
         let plus_cycle = item["+1"].as_bool().unwrap();
+        let mode = item["mode"].as_str().unwrap().trim().to_owned();
 
-        let instruction = format!("InstructionType::{}", mnemonic.to_uppercase());
+        instructions.insert(
+            op_code,
+            OpCode {
+                mnemonic,
+                op_code,
+                bytes,
+                cycles,
+                mode,
+                plus_cycle,
+            },
+        );
+    }
+    Ok(instructions)
+}
 
-        let memory_addressing = format!("{}", item["mode"].as_str().unwrap());
-        let memory_addressing = format!("MemoryAdressingMode::{}", memory_addressing);
+fn access(op_code: &OpCode) -> &'static str {
+    if op_code.mode == "Relative" {
+        return "Branch";
+    }
+    match op_code.mnemonic.as_str() {
+        "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" if op_code.mode == "Accumulator" => {
+            "Internal"
+        }
+        "NOP" if op_code.mode == "Implied" => "Internal",
+        "ADC" | "AND" | "BIT" | "CMP" | "CPX" | "CPY" | "EOR" | "LDA" | "LDX" | "LDY" | "ORA"
+        | "SBC" | "NOP" | "ALR" | "ANC" | "ARR" | "AXS" | "LAS" | "LAX" | "XAA" => "Read",
+        "STA" | "STX" | "STY" | "STZ" | "SAX" | "AHX" | "SHX" | "SHY" | "TAS" => "Write",
+        "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "TRB" | "TSB" | "DCP" | "ISB" | "RLA"
+        | "RRA" | "SLO" | "SRE" => "ReadModifyWrite",
+        "PHA" | "PHP" | "PHX" | "PHY" | "PLA" | "PLP" | "PLX" | "PLY" => "Stack",
+        "JMP" | "JSR" | "RTS" | "RTI" | "BRK" => "Jump",
+        _ => "Internal",
+    }
+}
 
-        let instruction = format!(
-            "    instruction!(\"{}\", {:#x}, {}, {}, {}, {}, {}),",
-            mnemonic, op_code, bytes, cycles, instruction, memory_addressing, plus_cycle
-        );
-        instructions.insert(op_code, instruction);
+fn penalty(op_code: &OpCode) -> &'static str {
+    match (op_code.mode.as_str(), op_code.plus_cycle) {
+        ("Relative", _) => "Branch",
+        (_, true) => "PageCross",
+        (_, false) => "None",
+    }
+}
+
+fn nmos_stability(op_code: &OpCode) -> &'static str {
+    match (op_code.mnemonic.as_str(), op_code.op_code) {
+        ("NOP", 0xea) => "Official",
+        // The results of these depend on the chip, its temperature and the bus
+        ("XAA", _) | ("LAX", 0xab) | ("AHX", _) | ("SHX", _) | ("SHY", _) | ("TAS", _) => {
+            "Unstable"
+        }
+        ("NOP", _)
+        | ("SBC", 0xeb)
+        | ("ALR", _)
+        | ("ANC", _)
+        | ("ARR", _)
+        | ("AXS", _)
+        | ("DCP", _)
+        | ("ISB", _)
+        | ("KIL", _)
+        | ("LAS", _)
+        | ("LAX", _)
+        | ("RLA", _)
+        | ("RRA", _)
+        | ("SAX", _)
+        | ("SLO", _)
+        | ("SRE", _) => "Stable",
+        _ => "Official",
     }
+}
 
-    for i in 0..255 {
-        let instruction = match instructions.get(&i) {
-            Some(instruction) => instruction.to_owned(),
-            None => format!("    instruction!(\"NotImplemented\", {:#x}, 0, 0, InstructionType::NotImplemented, MemoryAdressingMode::Immediate, false),", i),
-        };
-        scope.raw(&instruction);
+// Every NMOS unofficial opcode was replaced, only the undocumented NOPs are left
+fn cmos_stability(op_code: &OpCode) -> &'static str {
+    match (op_code.mnemonic.as_str(), op_code.op_code) {
+        ("NOP", 0xea) => "Official",
+        ("NOP", _) => "Stable",
+        _ => "Official",
     }
+}
 
+fn write_instruction_set(
+    scope: &mut Scope,
+    name: &str,
+    instructions: &HashMap<u8, OpCode>,
+    stability: fn(&OpCode) -> &'static str,
+) {
+    scope.raw(&format!("pub const {}: [Instruction; 256] = [", name));
+    for i in 0..=255 {
+        let op_code = instructions
+            .get(&i)
+            .unwrap_or_else(|| panic!("{} is missing opcode {:#04x}", name, i));
+        scope.raw(&format!(
+            "    instruction!(\"{}\", {:#x}, {}, {}, InstructionType::{}, MemoryAdressingMode::{}, Access::{}, Stability::{}, Penalty::{}),",
+            op_code.mnemonic,
+            op_code.op_code,
+            op_code.bytes,
+            op_code.cycles,
+            op_code.mnemonic,
+            op_code.mode,
+            access(op_code),
+            stability(op_code),
+            penalty(op_code)
+        ));
+    }
     scope.raw("];");
+}
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    for file in OPCODE_FILES {
+        println!("cargo:rerun-if-changed={}", file);
+    }
+
+    let instructions = load_instructions(OPCODE_FILES[0])?;
+
+    // The 65C02 reuses the NMOS table with its own opcodes (and every unofficial NMOS one) replaced
+    let mut instructions_65c02 = instructions.clone();
+    instructions_65c02.extend(load_instructions(OPCODE_FILES[1])?);
+
+    let mut scope = Scope::new();
+    for import in [
+        "Access",
+        "Instruction",
+        "InstructionType",
+        "MemoryAdressingMode",
+        "Penalty",
+        "Stability",
+    ] {
+        scope.import("super", import);
+    }
+
+    write_instruction_set(&mut scope, "INSTRUCTION_SET", &instructions, nmos_stability);
+    write_instruction_set(
+        &mut scope,
+        "INSTRUCTION_SET_65C02",
+        &instructions_65c02,
+        cmos_stability,
+    );
 
-    // scope.raw("impl Into<u8> for InstructionType {");
-    // scope.raw("fn into(self) -> u8 {");
-    // scope.raw("match self {");
-    // for item in value.as_array().unwrap() {
-    //     let mnemonic = item["name"].as_str().unwrap();
-    //     let op_code = item["opcode"].as_str().unwrap().to_owned().replace("$", "");
-    //     let op_code = u8::from_str_radix(&op_code, 16).unwrap();
-
-    //     let instruction = format!("InstructionType::{}", mnemonic.to_uppercase());
-    //     let op_code = format!("{:#x}", op_code);
-    //     let match_case = format!("{} => {},", instruction, op_code);
-    //     scope.raw(&match_case);
-    // }
-    // scope.raw("}");
-    // scope.raw("}");
-    // scope.raw("}");
-
-    write("src/cpu/instructions/instruction_set.rs", scope.to_string()).unwrap();
-    std::process::Command::new("cargo")
-        .arg("fmt")
-        .output()
-        .expect("Format failed");
+    let out_dir = env::var("OUT_DIR").unwrap();
+    write(
+        Path::new(&out_dir).join("instruction_set.rs"),
+        scope.to_string(),
+    )
+    .unwrap();
     Ok(())
 }
@@ -0,0 +1,37 @@
+extern crate nes;
+
+use nes::cpu::trace::first_divergence;
+use std::{env, fs::File, io::BufReader, process};
+
+fn open(path: &str) -> BufReader<File> {
+    let file = File::open(path).unwrap_or_else(|err| {
+        eprintln!("could not open {}: {}", path, err);
+        process::exit(2);
+    });
+    BufReader::new(file)
+}
+
+// Reports the first line where our trace stops matching a reference log (e.g. nestest.log)
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <our trace> <reference log>", args[0]);
+        process::exit(2);
+    }
+
+    match first_divergence(open(&args[1]), open(&args[2])).expect("could not read traces") {
+        None => println!("traces match"),
+        Some(divergence) => {
+            println!("traces diverge at line {}", divergence.line);
+            println!(
+                "ours:      {}",
+                divergence.ours.as_deref().unwrap_or("<end of trace>")
+            );
+            println!(
+                "reference: {}",
+                divergence.reference.as_deref().unwrap_or("<end of trace>")
+            );
+            process::exit(1);
+        }
+    }
+}
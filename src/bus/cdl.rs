@@ -0,0 +1,266 @@
+use std::{fs, io, path::Path};
+
+use crate::rom::Rom;
+
+/*
+Code/Data Logger, in the format FCEUX uses for its .cdl files: one byte per PRG ROM
+byte followed by one byte per CHR ROM byte, no header.
+
+PRG  xPdcAADC  C code, D data, AA the 8K window ($8000, $A000, $C000, $E000) it was
+               last read through, c the target of an indirect jump, d read through a
+               pointer (LDA ($nn),Y), P DMC sample data (not set yet, there is no APU)
+CHR  xxxxxxRD  D rendered, R read through $2007
+*/
+
+bitflags! {
+    #[derive(Default)]
+    pub struct PrgUsage: u8 {
+        const CODE          = 0b0000_0001;
+        const DATA          = 0b0000_0010;
+        const BANK          = 0b0000_1100;
+        const INDIRECT_CODE = 0b0001_0000;
+        const INDIRECT_DATA = 0b0010_0000;
+        const PCM           = 0b0100_0000;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct ChrUsage: u8 {
+        const RENDERED = 0b0000_0001;
+        const READ     = 0b0000_0010;
+    }
+}
+
+impl PrgUsage {
+    // Which 8K window of the CPU address space the byte was read through
+    pub fn bank(&self) -> u8 {
+        (self.bits() & Self::BANK.bits()) >> 2
+    }
+
+    fn with_bank(self, address: u16) -> Self {
+        let bank = ((address >> 13) & 0b11) as u8;
+        (self - Self::BANK) | Self::from_bits_truncate(bank << 2)
+    }
+
+    fn is_used(&self) -> bool {
+        !(*self - Self::BANK).is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeDataLog {
+    prg: Vec<PrgUsage>,
+    chr: Vec<ChrUsage>,
+}
+
+impl CodeDataLog {
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        Self {
+            prg: vec![PrgUsage::empty(); prg_size],
+            chr: vec![ChrUsage::empty(); chr_size],
+        }
+    }
+
+    pub fn for_rom(rom: &Rom) -> Self {
+        Self::new(rom.prg_rom.len(), rom.chr_rom.len())
+    }
+
+    // The file doesn't say where PRG ends, so it has to come from the ROM
+    pub fn from_bytes(bytes: &[u8], prg_size: usize) -> Result<Self, String> {
+        if bytes.len() < prg_size {
+            return Err(format!(
+                "code/data log is {} bytes, the PRG ROM alone is {}",
+                bytes.len(),
+                prg_size
+            ));
+        }
+        let (prg, chr) = bytes.split_at(prg_size);
+        Ok(Self {
+            prg: prg
+                .iter()
+                .map(|&bits| PrgUsage::from_bits_truncate(bits))
+                .collect(),
+            chr: chr
+                .iter()
+                .map(|&bits| ChrUsage::from_bits_truncate(bits))
+                .collect(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, rom: &Rom) -> Result<Self, String> {
+        let bytes = fs::read(&path).map_err(|err| err.to_string())?;
+        let log = Self::from_bytes(&bytes, rom.prg_rom.len())?;
+        if log.chr.len() != rom.chr_rom.len() {
+            return Err(format!(
+                "code/data log has {} bytes of CHR, the ROM has {}",
+                log.chr.len(),
+                rom.chr_rom.len()
+            ));
+        }
+        Ok(log)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.prg
+            .iter()
+            .map(PrgUsage::bits)
+            .chain(self.chr.iter().map(ChrUsage::bits))
+            .collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    // Adds what another session saw. Where both saw a byte our bank number is kept.
+    pub fn merge(&mut self, other: &CodeDataLog) -> Result<(), String> {
+        if self.prg.len() != other.prg.len() || self.chr.len() != other.chr.len() {
+            return Err("code/data logs are for different ROMs".to_owned());
+        }
+        for (ours, theirs) in self.prg.iter_mut().zip(&other.prg) {
+            *ours = if ours.is_used() {
+                *ours | (*theirs - PrgUsage::BANK)
+            } else {
+                *theirs
+            };
+        }
+        for (ours, theirs) in self.chr.iter_mut().zip(&other.chr) {
+            *ours |= *theirs;
+        }
+        Ok(())
+    }
+
+    pub fn prg(&self) -> &[PrgUsage] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[ChrUsage] {
+        &self.chr
+    }
+
+    // Takes the CPU address as well for the bank bits
+    pub fn log_prg(&mut self, offset: usize, address: u16, usage: PrgUsage) {
+        if let Some(entry) = self.prg.get_mut(offset) {
+            *entry = (*entry | usage).with_bank(address);
+        }
+    }
+
+    pub fn log_chr(&mut self, offset: usize, usage: ChrUsage) {
+        if let Some(entry) = self.chr.get_mut(offset) {
+            *entry |= usage;
+        }
+    }
+
+    pub(crate) fn take_chr(&mut self) -> Vec<ChrUsage> {
+        std::mem::take(&mut self.chr)
+    }
+
+    pub(crate) fn set_chr(&mut self, chr: Vec<ChrUsage>) {
+        self.chr = chr;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+    use crate::bus::MemoryBus;
+    use crate::cpu::{
+        disasm::{disassemble_logged, Listing},
+        variant::CpuVariant,
+        CPU,
+    };
+
+    #[test]
+    fn test_log_prg_banks() {
+        let mut log = CodeDataLog::new(0x8000, 0x2000);
+        log.log_prg(0x4000, 0xc000, PrgUsage::CODE);
+        log.log_prg(0x4000, 0xe000, PrgUsage::DATA | PrgUsage::INDIRECT_DATA);
+        assert_eq!(log.prg()[0x4000].bank(), 3);
+        assert_eq!(log.to_bytes()[0x4000], 0b0010_1111);
+
+        // Out of range offsets (a mapper we don't know about) are ignored
+        log.log_prg(0x8000, 0x8000, PrgUsage::CODE);
+        log.log_chr(0x2000, ChrUsage::READ);
+        assert_eq!(log.to_bytes().len(), 0xa000);
+    }
+
+    #[test]
+    fn test_bytes_and_merge() {
+        let mut ours = CodeDataLog::from_bytes(&[0x01, 0x00, 0x0a, 0x01], 3).unwrap();
+        assert_eq!(ours.chr(), [ChrUsage::RENDERED]);
+        assert!(ours.prg()[2].contains(PrgUsage::DATA));
+        assert_eq!(ours.prg()[2].bank(), 2);
+
+        let theirs = CodeDataLog::from_bytes(&[0x0e, 0x51, 0x00, 0x02], 3).unwrap();
+        ours.merge(&theirs).unwrap();
+        assert_eq!(ours.to_bytes(), [0x03, 0x51, 0x0a, 0x03]);
+
+        assert!(CodeDataLog::from_bytes(&[0x00], 2).is_err());
+        assert!(ours.merge(&CodeDataLog::new(3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_logging_a_program() {
+        let program = assemble!(
+            "        lda #$00",
+            "        sta $2006",
+            "        lda #$10",
+            "        sta $2006",
+            "        lda $2007",
+            "        ldx #1",
+            "        lda table,x",
+            "        lda #<table",
+            "        sta $10",
+            "        lda #>table",
+            "        sta $11",
+            "        ldy #2",
+            "        lda ($10),y",
+            "        jmp (vector)",
+            "table:  .byte 1, 2, 3",
+            "vector: .word target",
+            "        .byte $ea",
+            "target: kil",
+        );
+        let symbol = |name| program.symbol(name).unwrap() as usize - 0x8000;
+        let rom = program.rom().unwrap();
+        let mut cpu = CPU::new(MemoryBus::new(program.rom().unwrap()));
+        cpu.bus
+            .start_code_data_log(CodeDataLog::for_rom(&rom))
+            .unwrap();
+        cpu.run_until(|_| false);
+        let log = cpu.bus.stop_code_data_log().unwrap();
+
+        let prg = log.prg();
+        assert_eq!(prg[0], PrgUsage::CODE);
+        assert_eq!(prg[symbol("table")], PrgUsage::empty());
+        assert_eq!(prg[symbol("table") + 1], PrgUsage::DATA);
+        assert_eq!(
+            prg[symbol("table") + 2],
+            PrgUsage::DATA | PrgUsage::INDIRECT_DATA
+        );
+        assert_eq!(prg[symbol("vector") + 1], PrgUsage::DATA);
+        assert_eq!(
+            prg[symbol("target")],
+            PrgUsage::CODE | PrgUsage::INDIRECT_CODE
+        );
+        assert_eq!(log.chr()[0x10], ChrUsage::READ);
+        assert!(cpu.bus.code_data_log().is_none());
+
+        let end = symbol("target") + 1;
+        let listing: Vec<String> = disassemble_logged(
+            &rom.prg_rom[..end],
+            &prg[..end],
+            0x8000,
+            CpuVariant::Ricoh2A03,
+        )
+        .iter()
+        .map(Listing::to_string)
+        .collect();
+        assert_eq!(
+            listing[listing.len() - 3..],
+            ["JMP ($8024)", ".byte $01, $02, $03, $27, $80, $EA", "KIL"].map(str::to_owned)[..]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use super::Bus;
+
+// 64 KiB of plain RAM with the interrupt lines under the caller's control.
+// Good enough for generic 6502 programs and test harnesses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatRam {
+    memory: Vec<u8>,
+    cycles: u64,
+    irq: bool,
+    nmi_pending: bool,
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+            cycles: 0,
+            irq: false,
+            nmi_pending: false,
+        }
+    }
+
+    pub fn load(&mut self, address: u16, data: &[u8]) {
+        let start = address as usize;
+        assert!(
+            start + data.len() <= self.memory.len(),
+            "{} bytes at {:#06x} do not fit in memory",
+            data.len(),
+            address
+        );
+        self.memory[start..start + data.len()].copy_from_slice(data);
+    }
+
+    pub fn write_word(&mut self, address: u16, word: u16) {
+        self.memory[address as usize] = (word & 0xff) as u8;
+        self.memory[address.wrapping_add(1) as usize] = (word >> 8) as u8;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_irq(&mut self, active: bool) {
+        self.irq = active;
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+}
+
+impl Bus for FlatRam {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+    }
+
+    fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    fn irq_line(&self) -> bool {
+        self.irq
+    }
+}
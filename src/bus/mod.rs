@@ -1,12 +1,168 @@
-use crate::cpu::interrupt::InterruptType;
+pub mod cdl;
+pub mod flat_ram;
 
-#[cfg(test)]
-use crate::cpu::interrupt::{
-    IRQ_BRK_VECTOR, IRQ_BRK_VECTOR_END, NMI_VECTOR, NMI_VECTOR_END, RESET_VECTOR, RESET_VECTOR_END,
-};
+use std::fmt::Display;
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 
 use super::ppu::{PPUValue, PPU};
 use super::rom::Rom;
+use cdl::{CodeDataLog, PrgUsage};
+
+// Everything the CPU needs from the machine it is plugged into
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, data: u8);
+    // Same as read but without side effects, for debuggers and trace logs
+    fn peek(&self, address: u16) -> u8;
+    // Advances the rest of the machine, the CPU calls it right before each of its accesses
+    fn tick(&mut self, cycles: u8);
+
+    fn nmi_pending(&self) -> bool {
+        false
+    }
+    fn acknowledge_nmi(&mut self) {}
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    // Scanline, dot and frame count of the PPU, if there is one, for trace logs
+    // and breakpoint conditions
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        None
+    }
+    fn ppu_frame(&self) -> Option<u64> {
+        None
+    }
+
+    // Watchpoints on memory the CPU can't see directly (PPU address space, OAM) need
+    // the bus to keep a log of those accesses while they are set
+    fn record_accesses(&mut self, _enabled: bool) {}
+    fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        vec![]
+    }
+
+    // What the CPU made of a byte it read, for the code/data logger
+    fn log_code_data(&mut self, _address: u16, _usage: PrgUsage) {}
+
+    // Cycles the last access held the CPU off the bus for, which the bus already
+    // ticked through itself (OAM DMA)
+    fn take_stall(&mut self) -> u16 {
+        0
+    }
+
+    // The power switch and the reset button, the CPU takes care of its own registers
+    fn power_on(&mut self) {}
+    fn reset(&mut self) {}
+}
+
+// What RAM, OAM and palette RAM hold at power on. Real consoles come up with
+// whatever the chips settle on, which is neither documented nor the same twice,
+// and some games read it (as a random seed, or by mistake).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    Ones,
+    // Four $00 then four $FF, the pattern FCEUX uses and many consoles come close to
+    Stripes,
+    Random(u64),
+}
+
+impl RamInit {
+    pub fn fill(&self, memory: &mut [u8]) {
+        match self {
+            RamInit::Zeros => memory.fill(0),
+            RamInit::Ones => memory.fill(0xff),
+            RamInit::Stripes => {
+                for (index, byte) in memory.iter_mut().enumerate() {
+                    *byte = if index & 4 == 0 { 0x00 } else { 0xff };
+                }
+            }
+            // Mixed with the length so RAM and OAM don't start out the same
+            RamInit::Random(seed) => {
+                StdRng::seed_from_u64(seed ^ memory.len() as u64).fill_bytes(memory)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    Ppu,
+    Oam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryOperation {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub space: AddressSpace,
+    pub operation: MemoryOperation,
+    pub address: u16,
+    pub value: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    // Nothing we emulate answers there (APU, controllers, expansion and cartridge RAM)
+    Unmapped,
+    // A read of a write only register
+    WriteOnly,
+    // A write to $2002 or to PRG ROM
+    ReadOnly,
+    // A $2007 write into CHR ROM, the address is the PPU's
+    ChrRom,
+}
+
+// An access real hardware shrugs off but that likely points at a bug, reported in strict mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub operation: MemoryOperation,
+    pub address: u16,
+    // The value written, or the open bus value the read got back
+    pub value: u8,
+    pub cycle: u64,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            DiagnosticKind::Unmapped => "unmapped address",
+            DiagnosticKind::WriteOnly => "write only register",
+            DiagnosticKind::ReadOnly => "read only address",
+            DiagnosticKind::ChrRom => "CHR ROM",
+        };
+        match self.operation {
+            MemoryOperation::Read => write!(
+                f,
+                "cycle {}: read of {} ${:04X} got open bus ${:02X}",
+                self.cycle, kind, self.address, self.value
+            ),
+            MemoryOperation::Write => write!(
+                f,
+                "cycle {}: write of ${:02X} to {} ${:04X} ignored",
+                self.cycle, self.value, kind, self.address
+            ),
+        }
+    }
+}
+
+bitflags! {
+    // Everything that can hold the shared /IRQ line low
+    #[derive(Default)]
+    pub struct IrqSource: u8 {
+        const APU_FRAME_COUNTER = 0b0000_0001;
+        const APU_DMC           = 0b0000_0010;
+        const MAPPER            = 0b0000_0100;
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct MemoryBus {
@@ -14,6 +170,20 @@ pub struct MemoryBus {
     prg_rom: Vec<u8>,
     pub ppu: PPU,
     cycles: usize,
+    irq_sources: IrqSource,
+    nmi_line: bool,
+    nmi_pending: bool,
+    // The PRG half of the code/data log, the PPU keeps the CHR half
+    code_data_log: Option<CodeDataLog>,
+    // The last value on the CPU data bus, which is what reads of unmapped memory see
+    open_bus: u8,
+    // Only kept in strict mode
+    diagnostics: Option<Vec<Diagnostic>>,
+    ram_init: RamInit,
+    // Cycles spent on OAM DMA the CPU hasn't caught up with yet
+    stall: u16,
+    // Set when the PPU finishes a picture, for frontends that present it
+    frame_ready: bool,
 }
 
 const RAM: u16 = 0x0000;
@@ -24,69 +194,229 @@ const OAM_DMA: u16 = 0x4014;
 
 impl MemoryBus {
     pub fn new(rom: Rom) -> Self {
-        let ppu = PPU::new(rom.chr_rom, rom.screen_mirroring);
+        Self::new_with_ram_init(rom, RamInit::default())
+    }
+
+    pub fn new_with_ram_init(rom: Rom, ram_init: RamInit) -> Self {
+        let mut ppu = PPU::new(rom.chr_rom, rom.screen_mirroring);
+        ppu.power_on(ram_init);
+        let mut memory = [0; 2048];
+        ram_init.fill(&mut memory);
         Self {
-            memory: [0; 2048],
+            memory,
             prg_rom: rom.prg_rom,
             ppu,
             cycles: 0,
+            irq_sources: IrqSource::empty(),
+            nmi_line: false,
+            nmi_pending: false,
+            code_data_log: None,
+            open_bus: 0,
+            diagnostics: None,
+            ram_init,
+            stall: 0,
+            frame_ready: false,
         }
     }
 
-    pub fn poll_nmi_status(&self) -> Option<InterruptType> {
-        self.ppu.nmi_interrupt.clone()
+    pub fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    // Takes effect on the next power on
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    // Strict mode keeps a Diagnostic for every access to memory that isn't there.
+    // Either way such reads get the open bus value and such writes are dropped.
+    pub fn set_strict(&mut self, strict: bool) {
+        match (strict, &self.diagnostics) {
+            (true, None) => self.diagnostics = Some(vec![]),
+            (false, _) => self.diagnostics = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.diagnostics.is_some()
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    // Whether the PPU's frame buffer got a new picture since the last call
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    fn report(
+        &mut self,
+        kind: DiagnosticKind,
+        operation: MemoryOperation,
+        address: u16,
+        value: u8,
+    ) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.push(Diagnostic {
+                kind,
+                operation,
+                address,
+                value,
+                cycle: self.cycles as u64,
+            });
+        }
+    }
+
+    // Starts logging into log, which can be empty or one loaded from an earlier session
+    pub fn start_code_data_log(&mut self, mut log: CodeDataLog) -> Result<(), String> {
+        if log.prg().len() != self.prg_rom.len() || log.chr().len() != self.ppu.chr_rom_len() {
+            return Err("code/data log is for a different ROM".to_owned());
+        }
+        self.ppu.set_chr_usage(Some(log.take_chr()));
+        self.code_data_log = Some(log);
+        Ok(())
+    }
+
+    // A copy of the log so far
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        let mut log = self.code_data_log.clone()?;
+        log.set_chr(self.ppu.chr_usage().unwrap_or_default().to_vec());
+        Some(log)
+    }
+
+    pub fn stop_code_data_log(&mut self) -> Option<CodeDataLog> {
+        let mut log = self.code_data_log.take()?;
+        log.set_chr(self.ppu.take_chr_usage().unwrap_or_default());
+        Some(log)
+    }
+
+    // IRQ is level triggered: the line stays asserted for as long as any source holds it
+    pub fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.irq_sources.set(source, active);
     }
 
     pub fn read_byte(&mut self, address: u16) -> u8 {
-        match address {
+        let data = match address {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = address & 0b0000_0111_1111_1111;
                 self.memory[mirror_down_addr as usize]
             }
-            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END | OAM_DMA => {
-                self.ppu.read_register(address).into()
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                let register = address & 0x2007;
+                // The PPU answers these with its own latch rather than the CPU's open bus
+                if !matches!(register, 0x2002 | 0x2004 | 0x2007) {
+                    let latch = self.ppu.io_latch();
+                    self.report(
+                        DiagnosticKind::WriteOnly,
+                        MemoryOperation::Read,
+                        address,
+                        latch,
+                    );
+                }
+                let data = self.ppu.read_register(register).into();
+                if self.ppu.take_nmi_suppressed() {
+                    self.nmi_pending = false;
+                }
+                data
             }
             0x8000..=0xFFFF => self.read_from_rom(address),
+            OAM_DMA => {
+                let open_bus = self.open_bus;
+                self.report(
+                    DiagnosticKind::WriteOnly,
+                    MemoryOperation::Read,
+                    address,
+                    open_bus,
+                );
+                open_bus
+            }
             _ => {
-                panic!("Ignoring mem access at {:x} ({})", address, address);
+                let open_bus = self.open_bus;
+                self.report(
+                    DiagnosticKind::Unmapped,
+                    MemoryOperation::Read,
+                    address,
+                    open_bus,
+                );
+                open_bus
             }
-        }
+        };
+        self.open_bus = data;
+        data
     }
 
     pub fn write_byte(&mut self, address: u16, data: u8) {
+        self.open_bus = data;
         match address {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = address & 0b11111111111;
                 self.memory[mirror_down_addr as usize] = data;
             }
-            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END | OAM_DMA => {
-                self.ppu.write_register(address, PPUValue::Byte(data))
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                let register = address & 0x2007;
+                if register == 0x2002 {
+                    self.report(
+                        DiagnosticKind::ReadOnly,
+                        MemoryOperation::Write,
+                        address,
+                        data,
+                    );
+                }
+                self.ppu.write_register(register, PPUValue::Byte(data));
+                if let Some(chr_address) = self.ppu.take_dropped_write() {
+                    self.report(
+                        DiagnosticKind::ChrRom,
+                        MemoryOperation::Write,
+                        chr_address,
+                        data,
+                    );
+                }
             }
-            #[cfg(test)]
-            IRQ_BRK_VECTOR..=IRQ_BRK_VECTOR_END
-            | NMI_VECTOR..=NMI_VECTOR_END
-            | RESET_VECTOR..=RESET_VECTOR_END => {
-                let address = (address - 0x8000) as usize;
-                assert!(
-                    address <= self.prg_rom.len(),
-                    "addr: {} len: {}",
+            OAM_DMA => self.oam_dma(data),
+            0x8000..=0xFFFF => {
+                self.report(
+                    DiagnosticKind::ReadOnly,
+                    MemoryOperation::Write,
                     address,
-                    self.prg_rom.len()
-                );
-                println!(
-                    "Setting Handler up (only should be done for testing): {:x}",
-                    address
+                    data,
                 );
-                self.prg_rom[address as usize] = data
-            }
-            0x8000..=0xFFFF => {
-                panic!("Attempt to write to Cartridge ROM space: {:x}", address);
             }
+            _ => self.report(
+                DiagnosticKind::Unmapped,
+                MemoryOperation::Write,
+                address,
+                data,
+            ),
+        }
+    }
 
-            _ => {
-                println!("Ignoring mem write-access at {}", address);
-            }
+    /*
+    Copies page $XX00-$XXFF to OAM through $2004, so it starts at the current OAM
+    address and wraps around. The CPU is halted for a cycle, one more to line up
+    with a read cycle if the write landed on an odd one, then every byte takes a
+    read and a write cycle: 513 or 514 cycles in all, ticked here.
+    */
+    fn oam_dma(&mut self, page: u8) {
+        let cycles = if self.cycles % 2 == 1 { 514 } else { 513 };
+        for _ in 0..cycles - 512 {
+            self.tick(1);
         }
+        for offset in 0..=0xff {
+            self.tick(1);
+            let data = self.read_byte((page as u16) << 8 | offset);
+            self.tick(1);
+            self.write_byte(0x2004, data);
+        }
+        self.stall += cycles;
     }
 
     pub fn read_word(&mut self, address: u16) -> u16 {
@@ -102,37 +432,107 @@ impl MemoryBus {
         self.write_byte(address + 1, most_sig_bits);
     }
 
-    pub fn read_from_rom(&self, mut address: u16) -> u8 {
+    pub fn read_from_rom(&self, address: u16) -> u8 {
+        self.prg_rom[self.prg_rom_offset(address)]
+    }
+
+    fn prg_rom_offset(&self, mut address: u16) -> usize {
         address -= 0x8000;
         if self.prg_rom.len() == 0x4000 && address >= 0x4000 {
             //mirror if needed
             address %= 0x4000;
         }
-        self.prg_rom[address as usize]
+        address as usize
+    }
+}
+
+impl Bus for MemoryBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.write_byte(address, data)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            RAM..=RAM_MIRRORS_END => self.memory[(address & 0b0000_0111_1111_1111) as usize],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.peek_register(address & 0x2007),
+            0x8000..=0xFFFF => self.read_from_rom(address),
+            // Nothing we emulate answers here (APU, IO), Nintendulator shows these as $FF
+            _ => 0xff,
+        }
     }
 
-    pub fn tick(&mut self, cycles: u8) {
+    fn tick(&mut self, cycles: u8) {
         self.cycles += cycles as usize;
-        self.ppu.tick(cycles * 3);
+        self.frame_ready |= self.ppu.tick(cycles * 3);
+
+        let nmi_line = self.ppu.nmi_line();
+        if nmi_line && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = nmi_line;
     }
 
-    #[cfg(test)]
-    pub(super) fn write_interrupt_handler(
-        &mut self,
-        interrupt_type: InterruptType,
-        handler_addr: u16,
-        handler: Vec<u8>,
-    ) {
-        let start = handler_addr as usize;
-        let end = start + handler.len();
-        assert!(end <= self.prg_rom.len());
-        self.prg_rom[start..end].copy_from_slice(&handler);
-        let addr = match interrupt_type {
-            InterruptType::BRK => IRQ_BRK_VECTOR,
-            InterruptType::IRQ => IRQ_BRK_VECTOR,
-            InterruptType::NMI => NMI_VECTOR,
-        };
-        self.write_word(addr, handler_addr + self.prg_rom.len() as u16);
+    // NMI is edge triggered: a low to high transition latches it until the CPU services it
+    fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    fn irq_line(&self) -> bool {
+        !self.irq_sources.is_empty()
+    }
+
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        Some((self.ppu.scanline(), self.ppu.dot()))
+    }
+
+    fn ppu_frame(&self) -> Option<u64> {
+        Some(self.ppu.frame())
+    }
+
+    fn record_accesses(&mut self, enabled: bool) {
+        self.ppu.record_accesses(enabled);
+    }
+
+    fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.ppu.take_accesses()
+    }
+
+    fn log_code_data(&mut self, address: u16, usage: PrgUsage) {
+        if self.code_data_log.is_some() && address >= 0x8000 {
+            let offset = self.prg_rom_offset(address);
+            if let Some(log) = &mut self.code_data_log {
+                log.log_prg(offset, address, usage);
+            }
+        }
+    }
+
+    fn take_stall(&mut self) -> u16 {
+        std::mem::take(&mut self.stall)
+    }
+
+    fn power_on(&mut self) {
+        self.ram_init.fill(&mut self.memory);
+        self.ppu.power_on(self.ram_init);
+        self.irq_sources = IrqSource::empty();
+        self.nmi_line = false;
+        self.nmi_pending = false;
+        self.open_bus = 0;
+        self.frame_ready = false;
+    }
+
+    // RAM is left as it is, that is how games tell a reset from a power on
+    fn reset(&mut self) {
+        self.ppu.reset();
+        self.nmi_line = false;
+        self.nmi_pending = false;
     }
 }
 
@@ -166,4 +566,253 @@ mod test {
         let word = memory_bus.read_byte(0x800);
         assert_eq!(word, 0x01)
     }
+
+    #[test]
+    fn test_irq_line_sources() {
+        let mut memory_bus = MemoryBus::new(Rom {
+            prg_rom: vec![],
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+        });
+        assert!(!memory_bus.irq_line());
+        memory_bus.set_irq(IrqSource::APU_FRAME_COUNTER, true);
+        memory_bus.set_irq(IrqSource::MAPPER, true);
+        memory_bus.set_irq(IrqSource::APU_FRAME_COUNTER, false);
+        assert!(memory_bus.irq_line());
+        memory_bus.set_irq(IrqSource::MAPPER, false);
+        assert!(!memory_bus.irq_line());
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let program = crate::assemble!(
+            "lda $5000", // nothing there, the high byte of the operand is left on the bus
+            "sta $00",
+            "lda #$1f",
+            "sta $2002", // ignored, but it still sets the PPU latch
+            "lda $2000",
+            "sta $01",
+            "lda $3ffa", // a mirror of $2002
+            "sta $02",
+            "sta $8000",
+            "kil",
+        );
+        let mut cpu = crate::cpu::CPU::new(MemoryBus::new(program.rom().unwrap()));
+        cpu.bus.set_strict(true);
+        cpu.run_until(|_| false);
+
+        assert_eq!(cpu.bus.read_byte(0x00), 0x50);
+        assert_eq!(cpu.bus.read_byte(0x01), 0x1f);
+        assert_eq!(cpu.bus.read_byte(0x02) & 0x1f, 0x1f);
+
+        let diagnostics = cpu.bus.take_diagnostics();
+        let kinds: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.kind, diagnostic.address))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (DiagnosticKind::Unmapped, 0x5000),
+                (DiagnosticKind::ReadOnly, 0x2002),
+                (DiagnosticKind::WriteOnly, 0x2000),
+                (DiagnosticKind::ReadOnly, 0x8000),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            format!(
+                "cycle {}: read of unmapped address $5000 got open bus $50",
+                diagnostics[0].cycle
+            )
+        );
+        assert!(cpu.bus.take_diagnostics().is_empty());
+
+        cpu.bus.set_strict(false);
+        cpu.bus.write_byte(0x4018, 0x12);
+        assert_eq!(cpu.bus.read_byte(0x6000), 0x12);
+        assert!(cpu.bus.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_chr_writes() {
+        let program = crate::assemble!(
+            "lda #0",
+            "sta $2006",
+            "sta $2006",
+            "lda #$55",
+            "sta $2007",
+            "kil",
+        );
+        let mut cpu = crate::cpu::CPU::new(MemoryBus::new(program.rom().unwrap()));
+        cpu.bus.set_strict(true);
+        cpu.run_until(|_| false);
+        let diagnostics = cpu.bus.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            (diagnostics[0].kind, diagnostics[0].address),
+            (DiagnosticKind::ChrRom, 0x0000)
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            format!(
+                "cycle {}: write of $55 to CHR ROM $0000 ignored",
+                diagnostics[0].cycle
+            )
+        );
+
+        // Without CHR ROM the cartridge has CHR RAM, which keeps the write
+        let mut memory_bus = MemoryBus::new(Rom {
+            prg_rom: vec![],
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+        });
+        memory_bus.set_strict(true);
+        for (register, data) in [(0x2006, 0x10), (0x2006, 0x20), (0x2007, 0x55)] {
+            memory_bus.write_byte(register, data);
+        }
+        memory_bus.write_byte(0x2006, 0x10);
+        memory_bus.write_byte(0x2006, 0x20);
+        memory_bus.read_byte(0x2007);
+        assert_eq!(memory_bus.read_byte(0x2007), 0x55);
+        assert!(memory_bus.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_power_on_and_reset() {
+        let program = crate::assemble!(
+            "       lda #$42",
+            "       sta $14",
+            "       ldx #$03",
+            "loop:  jmp loop",
+        );
+        let bus = MemoryBus::new_with_ram_init(program.rom().unwrap(), RamInit::Stripes);
+        let mut cpu = crate::cpu::CPU::new(bus);
+        assert_eq!((cpu.bus.peek(0x03), cpu.bus.peek(0x04)), (0x00, 0xff));
+        assert_eq!((cpu.stack_pointer, cpu.program_counter), (0xfd, 0x8000));
+        assert!(cpu
+            .processor_status
+            .contains(crate::cpu::processor_status::ProcessorStatus::INTERRUPT_DISABLE));
+
+        (0..3).for_each(|_| {
+            cpu.step();
+        });
+        cpu.reset();
+        assert_eq!((cpu.a, cpu.x), (0x42, 0x03));
+        assert_eq!((cpu.stack_pointer, cpu.program_counter), (0xfa, 0x8000));
+        assert_eq!(cpu.bus.peek(0x14), 0x42);
+
+        cpu.power_on();
+        assert_eq!((cpu.a, cpu.x, cpu.stack_pointer), (0x00, 0x00, 0xfd));
+        assert_eq!(cpu.bus.peek(0x14), 0xff);
+
+        let (mut first, mut second) = ([0; 16], [0; 16]);
+        RamInit::Random(7).fill(&mut first);
+        RamInit::Random(7).fill(&mut second);
+        assert_eq!(first, second);
+        assert_ne!(first, [0; 16]);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        // An odd write cycle waits one more cycle to line up, the 3 cycle LDA puts
+        // the write on an even one
+        let dma = |delay: &str| {
+            let program = crate::assemble!(
+                "        lda #$02",
+                "        sta $2003",
+                delay,
+                "        lda #$03",
+                "        sta $4014",
+            );
+            let mut cpu = crate::cpu::CPU::new(MemoryBus::new(program.rom().unwrap()));
+            for offset in 0..=0xff {
+                cpu.bus.write_byte(0x0300 + offset, offset as u8);
+            }
+            (0..4).for_each(|_| {
+                cpu.step();
+            });
+            // The write is the last of the STA's 4 cycles
+            let write_cycle = cpu.cycle + 4;
+            (write_cycle, cpu.step().cycles, cpu)
+        };
+
+        // 7 reset cycles, then 2 + 4 + 2 + 2 + 4: the write is on cycle 21
+        let (write_cycle, cycles, _) = dma("        nop");
+        assert_eq!((write_cycle, cycles), (21, 4 + 514));
+        // 2 + 4 + 3 + 2 + 4: on cycle 22
+        let (write_cycle, cycles, mut cpu) = dma("        lda $00");
+        assert_eq!((write_cycle, cycles), (22, 4 + 513));
+
+        // Written from OAM address 2 on, wrapping around
+        for (address, value) in [(0x02, 0x00), (0xff, 0xfd), (0x00, 0xfe), (0x01, 0xff)] {
+            cpu.bus.write_byte(0x2003, address);
+            assert_eq!(cpu.bus.read_byte(0x2004), value);
+        }
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let program = crate::assemble!(
+            "reset: lda #$80",
+            "       sta $2000",
+            "loop:  jmp loop",
+            "nmi:   inc $10",
+            "       rti",
+        );
+        let mut cpu = crate::cpu::CPU::new(MemoryBus::new(program.rom().unwrap()));
+        (0..3).for_each(|_| {
+            cpu.run_frame();
+        });
+        assert_eq!(cpu.bus.peek(0x10), 3);
+
+        let mut bus = MemoryBus::new(program.rom().unwrap());
+        let run_to = |bus: &mut MemoryBus, scanline, dot| {
+            while bus.ppu_position() != Some((scanline, dot)) {
+                bus.ppu.tick(1);
+            }
+        };
+        // The flag goes up at dot 1 of line 241 with or without NMIs
+        run_to(&mut bus, 240, 340);
+        bus.tick(1);
+        assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+        assert!(!bus.nmi_pending());
+        assert!(bus.take_frame_ready());
+        assert!(!bus.take_frame_ready());
+        // Enabling NMIs during vblank is an edge too
+        bus.write_byte(0x2000, 0x80);
+        bus.tick(1);
+        assert!(bus.nmi_pending());
+        bus.acknowledge_nmi();
+        // Reading clears it until the pre-render line
+        assert_eq!(bus.read_byte(0x2002) & 0x80, 0x80);
+        assert_eq!(bus.read_byte(0x2002) & 0x80, 0x00);
+        bus.write_byte(0x2000, 0x00);
+        bus.write_byte(0x2000, 0x80);
+        bus.tick(1);
+        assert!(!bus.nmi_pending());
+
+        // A read on the dot it's set reads it set but loses the NMI
+        run_to(&mut bus, 240, 340);
+        bus.tick(1);
+        assert!(bus.nmi_pending());
+        assert_eq!(bus.read_byte(0x2002) & 0x80, 0x80);
+        assert!(!bus.nmi_pending());
+
+        // One dot early, the flag isn't set at all that frame
+        run_to(&mut bus, 241, 1);
+        assert_eq!(bus.read_byte(0x2002) & 0x80, 0x00);
+        bus.tick(1);
+        assert_eq!(bus.peek(0x2002) & 0x80, 0x00);
+        assert!(!bus.nmi_pending());
+
+        // Without any reads it stays up until the pre-render line
+        run_to(&mut bus, 241, 2);
+        run_to(&mut bus, 261, 1);
+        assert_eq!(bus.peek(0x2002) & 0x80, 0x80);
+        bus.ppu.tick(1);
+        assert_eq!(bus.peek(0x2002) & 0x80, 0x00);
+    }
 }
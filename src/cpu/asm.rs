@@ -0,0 +1,782 @@
+/*
+A small two pass 6502 assembler built on the same instruction tables the CPU runs,
+so tests and experiments can be written as assembly text instead of opcode bytes.
+
+    ; comments run to the end of the line
+    SCREEN = $0200          ; constants, defined before they are used
+            .org $8000      ; code starts at $8000 unless told otherwise
+    reset:  ldx #<SCREEN    ; labels end in a colon
+    loop:   sta SCREEN,x
+            inx
+            bne loop        ; branch targets are addresses, the offset is worked out
+            jmp (vector)
+    vector: .word reset, $1234
+            .byte 1, 2, "text"
+
+Operands use the usual syntax: #imm, A, zp, abs, zp/abs,X, zp/abs,Y, (ind), (zp,X),
+(zp),Y and the 65C02 (zp) and (abs,X). Zero page is used whenever the value is known
+to fit by the time the line is reached; forward references always get the absolute
+form. An operand starting with ( is always indirect.
+
+Expressions are numbers ($hex, %binary, decimal, 'c'), symbols, * (the address of
+the current line) and + - * / & | ^ << >> with the unary - ~ < (low byte) and
+> (high byte).
+*/
+use std::collections::HashMap;
+
+use super::{
+    expr::{self, describe, expected_value, Syntax, Token},
+    instructions::{Instruction, InstructionType, MemoryAdressingMode},
+    variant::CpuVariant,
+};
+use crate::bus::flat_ram::FlatRam;
+use crate::rom::{Mirroring, Rom};
+
+const DEFAULT_ORIGIN: u16 = 0x8000;
+const PRG_ROM_START: u16 = 0x8000;
+const PRG_ROM_SIZE: usize = 0x8000;
+const CHR_ROM_SIZE: usize = 0x2000;
+// Labels that fill in the vectors at the top of PRG ROM
+const VECTORS: [(&str, u16); 3] = [("nmi", 0xfffa), ("reset", 0xfffc), ("irq", 0xfffe)];
+
+/*
+Assembles the lines, panicking with the assembler error if there is one:
+
+    let program = assemble!("lda #$05", "sta $0200", "kil");
+    let program = assemble!(CpuVariant::Cmos65C02; "stz $0200", "bra *");
+*/
+#[macro_export]
+macro_rules! assemble {
+    ($variant:expr; $($line:expr),+ $(,)?) => {
+        $crate::cpu::asm::assemble_for(&[$($line),+].join("\n"), $variant)
+            .unwrap_or_else(|error| ::std::panic!("{}", error))
+    };
+    ($($line:expr),+ $(,)?) => {
+        $crate::cpu::asm::assemble(&[$($line),+].join("\n"))
+            .unwrap_or_else(|error| ::std::panic!("{}", error))
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    // One per .org, in source order
+    segments: Vec<Segment>,
+    symbols: HashMap<String, i64>,
+}
+
+// Assembles for the NES CPU, unofficial opcodes included
+pub fn assemble(source: &str) -> Result<Program, String> {
+    assemble_for(source, CpuVariant::default())
+}
+
+pub fn assemble_for(source: &str, variant: CpuVariant) -> Result<Program, String> {
+    let mut assembler = Assembler::new(variant);
+    for (index, line) in source.lines().enumerate() {
+        assembler
+            .first_pass(line)
+            .map_err(|error| format!("line {}: {}", index + 1, error))?;
+    }
+    assembler.second_pass()
+}
+
+impl Program {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    // The address of a label or the value of a constant
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).map(|&value| value as u16)
+    }
+
+    pub fn load_into(&self, ram: &mut FlatRam) {
+        for segment in &self.segments {
+            ram.load(segment.origin, &segment.bytes);
+        }
+    }
+
+    /*
+    A 32 KiB image of $8000-$FFFF, unused space filled with $FF. Vectors the
+    program did not write itself point at the nmi, reset and irq labels, reset
+    falls back to the start of the first segment.
+    */
+    pub fn prg_rom(&self) -> Result<Vec<u8>, String> {
+        let mut image = vec![0xff; PRG_ROM_SIZE];
+        let mut written = vec![false; PRG_ROM_SIZE];
+        for segment in self.segments.iter().filter(|s| !s.bytes.is_empty()) {
+            if segment.origin < PRG_ROM_START {
+                return Err(format!(
+                    "segment at ${:04X} is outside of PRG ROM ($8000-$FFFF)",
+                    segment.origin
+                ));
+            }
+            let start = (segment.origin - PRG_ROM_START) as usize;
+            image[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+            written[start..start + segment.bytes.len()].fill(true);
+        }
+
+        let first_segment = self
+            .segments
+            .iter()
+            .find(|s| !s.bytes.is_empty())
+            .map(|s| s.origin);
+        for (label, vector) in VECTORS {
+            let offset = (vector - PRG_ROM_START) as usize;
+            if written[offset] || written[offset + 1] {
+                continue;
+            }
+            let target = match (self.symbol(label), label) {
+                (Some(target), _) => target,
+                (None, "reset") => match first_segment {
+                    Some(origin) => origin,
+                    None => continue,
+                },
+                (None, _) => continue,
+            };
+            image[offset] = (target & 0xff) as u8;
+            image[offset + 1] = (target >> 8) as u8;
+        }
+        Ok(image)
+    }
+
+    // An NROM cartridge with the program as PRG ROM and blank CHR ROM
+    pub fn rom(&self) -> Result<Rom, String> {
+        Ok(Rom {
+            prg_rom: self.prg_rom()?,
+            chr_rom: vec![0; CHR_ROM_SIZE],
+            mapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Symbol(String),
+    ProgramCounter,
+}
+
+type Expr = expr::Expr<Term>;
+type Parser = expr::Parser<AsmSyntax>;
+
+struct AsmSyntax;
+
+impl Syntax for AsmSyntax {
+    type Term = Term;
+    const PUNCTUATION: &'static [&'static str] = &[
+        "<<", ">>", "#", "(", ")", ",", ":", "=", "+", "-", "*", "/", "&", "|", "^", "~", "<", ">",
+    ];
+    const BINARY_OPERATORS: &'static [&'static [&'static str]] = &[
+        &["|"],
+        &["^"],
+        &["&"],
+        &["<<", ">>"],
+        &["+", "-"],
+        &["*", "/"],
+    ];
+    const UNARY_OPERATORS: &'static [&'static str] = &["-", "~", "<", ">"];
+    const VALUE: &'static str = "an expression";
+
+    fn term(_: &mut Parser, token: Token) -> Result<Expr, String> {
+        match token {
+            Token::Name(name) if !name.starts_with('.') => Ok(Expr::Term(Term::Symbol(name))),
+            Token::Punct("*") => Ok(Expr::Term(Term::ProgramCounter)),
+            token => Err(expected_value::<Self>(&token)),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, symbols: &HashMap<String, i64>, address: u16) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Number(value) => *value,
+            Expr::Term(Term::Symbol(name)) => *symbols
+                .get(name)
+                .ok_or_else(|| format!("undefined symbol {}", name))?,
+            Expr::Term(Term::ProgramCounter) => address as i64,
+            Expr::Unary(operator, operand) => {
+                let value = operand.eval(symbols, address)?;
+                match *operator {
+                    "-" => value.wrapping_neg(),
+                    "~" => !value,
+                    "<" => value & 0xff,
+                    ">" => (value >> 8) & 0xff,
+                    _ => unreachable!("unknown unary operator {}", operator),
+                }
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = left.eval(symbols, address)?;
+                let right = right.eval(symbols, address)?;
+                match *operator {
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left.checked_div(right).ok_or("division by zero")?,
+                    "&" => left & right,
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "<<" | ">>" if !(0..64).contains(&right) => {
+                        return Err(format!("cannot shift by {}", right))
+                    }
+                    "<<" => left << right,
+                    ">>" => left >> right,
+                    _ => unreachable!("unknown binary operator {}", operator),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr),
+    DirectX(Expr),
+    DirectY(Expr),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+impl Parser {
+    // Consumes X or Y
+    fn eat_register(&mut self, register: &str) -> bool {
+        match self.peek() {
+            Some(Token::Name(name)) if name.eq_ignore_ascii_case(register) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        if self.at_end() {
+            return Ok(Operand::None);
+        }
+        if self.tokens.len() - self.position == 1 && self.eat_register("a") {
+            return Ok(Operand::Accumulator);
+        }
+        if self.eat("#") {
+            return Ok(Operand::Immediate(self.expression()?));
+        }
+        if self.eat("(") {
+            let expr = self.expression()?;
+            if self.eat(",") {
+                if !self.eat_register("x") {
+                    return Err("expected X".to_owned());
+                }
+                self.expect(")")?;
+                return Ok(Operand::IndirectX(expr));
+            }
+            self.expect(")")?;
+            if self.eat(",") {
+                if !self.eat_register("y") {
+                    return Err("expected Y".to_owned());
+                }
+                return Ok(Operand::IndirectY(expr));
+            }
+            return Ok(Operand::Indirect(expr));
+        }
+
+        let expr = self.expression()?;
+        if !self.eat(",") {
+            return Ok(Operand::Direct(expr));
+        }
+        if self.eat_register("x") {
+            Ok(Operand::DirectX(expr))
+        } else if self.eat_register("y") {
+            Ok(Operand::DirectY(expr))
+        } else {
+            Err("expected X or Y".to_owned())
+        }
+    }
+}
+
+fn check_range(value: i64, min: i64, max: i64, what: &str) -> Result<i64, String> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} does not fit in {}", value, what))
+    }
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction(&'static Instruction, Option<Expr>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+// A statement placed by the first pass, encoded once every label is known
+#[derive(Debug)]
+struct Placed {
+    line: usize,
+    address: u16,
+    segment: usize,
+    statement: Statement,
+}
+
+struct Assembler {
+    variant: CpuVariant,
+    symbols: HashMap<String, i64>,
+    segments: Vec<Segment>,
+    placed: Vec<Placed>,
+    line: usize,
+    // u32 so running off the end of memory can be caught
+    address: u32,
+}
+
+impl Assembler {
+    fn new(variant: CpuVariant) -> Self {
+        Self {
+            variant,
+            symbols: HashMap::new(),
+            segments: vec![Segment {
+                origin: DEFAULT_ORIGIN,
+                bytes: vec![],
+            }],
+            placed: vec![],
+            line: 0,
+            address: DEFAULT_ORIGIN as u32,
+        }
+    }
+
+    fn define(&mut self, name: String, value: i64) -> Result<(), String> {
+        if self.symbols.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    // What an expression is worth right now, None while it refers to a later label
+    fn value_now(&self, expr: &Expr) -> Option<i64> {
+        expr.eval(&self.symbols, self.address as u16).ok()
+    }
+
+    fn first_pass(&mut self, line: &str) -> Result<(), String> {
+        self.line += 1;
+        let mut parser = Parser::new(line)?;
+
+        // label: or NAME = value
+        if let (Some(Token::Name(name)), Some(Token::Punct(punct @ (":" | "=")))) = (
+            parser.tokens.first().cloned(),
+            parser.tokens.get(1).cloned(),
+        ) {
+            parser.position = 2;
+            if punct == "=" {
+                let expr = parser.expression()?;
+                parser.expect_end()?;
+                let value = expr.eval(&self.symbols, self.address as u16)?;
+                return self.define(name, value);
+            }
+            self.define(name, self.address as i64)?;
+        }
+
+        let name = match parser.next() {
+            None => return Ok(()),
+            Some(Token::Name(name)) => name,
+            Some(token) => return Err(format!("unexpected {}", describe(&token))),
+        };
+        let statement = match name.to_ascii_lowercase().as_str() {
+            ".org" => {
+                let expr = parser.expression()?;
+                parser.expect_end()?;
+                let origin = check_range(
+                    expr.eval(&self.symbols, self.address as u16)?,
+                    0,
+                    0xffff,
+                    "an address",
+                )?;
+                self.segments.push(Segment {
+                    origin: origin as u16,
+                    bytes: vec![],
+                });
+                self.address = origin as u32;
+                return Ok(());
+            }
+            ".byte" | ".db" => Statement::Bytes(self.data(&mut parser, true)?),
+            ".word" | ".dw" => Statement::Words(self.data(&mut parser, false)?),
+            directive if directive.starts_with('.') => {
+                return Err(format!("unknown directive {}", name))
+            }
+            _ => {
+                let operand = parser.operand()?;
+                parser.expect_end()?;
+                self.instruction(&name, operand)?
+            }
+        };
+
+        let size = match &statement {
+            Statement::Instruction(instruction, _) => instruction.bytes as u32,
+            Statement::Bytes(items) => items.len() as u32,
+            Statement::Words(items) => items.len() as u32 * 2,
+        };
+        self.placed.push(Placed {
+            line: self.line,
+            address: self.address as u16,
+            segment: self.segments.len() - 1,
+            statement,
+        });
+        self.address += size;
+        if self.address > 0x10000 {
+            return Err("code runs past $FFFF".to_owned());
+        }
+        Ok(())
+    }
+
+    fn data(&self, parser: &mut Parser, bytes: bool) -> Result<Vec<Expr>, String> {
+        let mut items = vec![];
+        loop {
+            match parser.peek() {
+                Some(Token::Text(text)) if bytes => {
+                    items.extend(text.bytes().map(|byte| Expr::Number(byte as i64)));
+                    parser.position += 1;
+                }
+                _ => items.push(parser.expression()?),
+            }
+            if !parser.eat(",") {
+                parser.expect_end()?;
+                return Ok(items);
+            }
+        }
+    }
+
+    fn find(
+        &self,
+        instruction_type: InstructionType,
+        mode: MemoryAdressingMode,
+    ) -> Option<&'static Instruction> {
+        self.variant
+            .instruction_set()
+            .iter()
+            .filter(|instruction| {
+                instruction.instruction_type == instruction_type
+                    && instruction.memory_addressing_mode == mode
+            })
+            // Official before unofficial copies such as the other implied NOPs or SBC $EB
+            .min_by_key(|instruction| (instruction.stability, instruction.op_code))
+    }
+
+    fn instruction(&self, mnemonic: &str, operand: Operand) -> Result<Statement, String> {
+        let instruction_type = self
+            .variant
+            .instruction_set()
+            .iter()
+            .find(|instruction| instruction.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .map(|instruction| instruction.instruction_type)
+            .ok_or_else(|| format!("unknown instruction {}", mnemonic))?;
+        let find = |mode| self.find(instruction_type, mode);
+
+        // Zero page when the value is already known to fit, absolute otherwise
+        let zero_page_or_absolute = |expr: &Expr, zero_page, absolute| {
+            let fits = matches!(self.value_now(expr), Some(0..=0xff));
+            match (find(zero_page), find(absolute)) {
+                (Some(instruction), _) if fits => Some(instruction),
+                (_, Some(instruction)) => Some(instruction),
+                (instruction, None) => instruction,
+            }
+        };
+
+        let (instruction, expr) = match operand {
+            Operand::None => (
+                find(MemoryAdressingMode::Implied)
+                    .or_else(|| find(MemoryAdressingMode::Accumulator)),
+                None,
+            ),
+            Operand::Accumulator => (find(MemoryAdressingMode::Accumulator), None),
+            Operand::Immediate(expr) => (find(MemoryAdressingMode::Immediate), Some(expr)),
+            Operand::Direct(expr) => {
+                let instruction = find(MemoryAdressingMode::Relative).or_else(|| {
+                    zero_page_or_absolute(
+                        &expr,
+                        MemoryAdressingMode::ZeroPage,
+                        MemoryAdressingMode::Absolute,
+                    )
+                });
+                (instruction, Some(expr))
+            }
+            Operand::DirectX(expr) => (
+                zero_page_or_absolute(
+                    &expr,
+                    MemoryAdressingMode::ZeroPageX,
+                    MemoryAdressingMode::AbsoluteX,
+                ),
+                Some(expr),
+            ),
+            Operand::DirectY(expr) => (
+                zero_page_or_absolute(
+                    &expr,
+                    MemoryAdressingMode::ZeroPageY,
+                    MemoryAdressingMode::AbsoluteY,
+                ),
+                Some(expr),
+            ),
+            Operand::Indirect(expr) => (
+                find(MemoryAdressingMode::Indirect)
+                    .or_else(|| find(MemoryAdressingMode::ZeroPageIndirect)),
+                Some(expr),
+            ),
+            Operand::IndirectX(expr) => (
+                find(MemoryAdressingMode::IndirectX)
+                    .or_else(|| find(MemoryAdressingMode::AbsoluteIndirectX)),
+                Some(expr),
+            ),
+            Operand::IndirectY(expr) => (find(MemoryAdressingMode::IndirectY), Some(expr)),
+        };
+        let instruction = instruction
+            .ok_or_else(|| format!("{} does not support that addressing mode", mnemonic))?;
+        Ok(Statement::Instruction(instruction, expr))
+    }
+
+    fn second_pass(mut self) -> Result<Program, String> {
+        for placed in &self.placed {
+            let bytes = self
+                .encode(placed)
+                .map_err(|error| format!("line {}: {}", placed.line, error))?;
+            self.segments[placed.segment].bytes.extend(bytes);
+        }
+
+        let mut ranges: Vec<(u32, u32)> = self
+            .segments
+            .iter()
+            .filter(|segment| !segment.bytes.is_empty())
+            .map(|segment| {
+                let start = segment.origin as u32;
+                (start, start + segment.bytes.len() as u32)
+            })
+            .collect();
+        ranges.sort_unstable();
+        for pair in ranges.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(format!("code at ${:04X} overlaps earlier code", pair[1].0));
+            }
+        }
+
+        Ok(Program {
+            segments: self.segments,
+            symbols: self.symbols,
+        })
+    }
+
+    fn encode(&self, placed: &Placed) -> Result<Vec<u8>, String> {
+        let eval = |expr: &Expr| expr.eval(&self.symbols, placed.address);
+        let mut bytes = vec![];
+        match &placed.statement {
+            Statement::Bytes(items) => {
+                for item in items {
+                    bytes.push(check_range(eval(item)?, -128, 0xff, "a byte")? as u8);
+                }
+            }
+            Statement::Words(items) => {
+                for item in items {
+                    let word = check_range(eval(item)?, -0x8000, 0xffff, "a word")? as u16;
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            Statement::Instruction(instruction, expr) => {
+                bytes.push(instruction.op_code);
+                match (instruction.memory_addressing_mode, expr) {
+                    (MemoryAdressingMode::Relative, Some(expr)) => {
+                        let target = check_range(eval(expr)?, 0, 0xffff, "an address")?;
+                        let offset = target - (placed.address as i64 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(format!("branch to ${:04X} is out of range", target));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    (MemoryAdressingMode::Immediate, Some(expr)) => {
+                        bytes.push(check_range(eval(expr)?, -128, 0xff, "a byte")? as u8);
+                    }
+                    (_, Some(expr)) if instruction.bytes == 2 => {
+                        bytes.push(check_range(eval(expr)?, 0, 0xff, "zero page")? as u8);
+                    }
+                    (_, Some(expr)) => {
+                        let address = check_range(eval(expr)?, 0, 0xffff, "an address")? as u16;
+                        bytes.extend_from_slice(&address.to_le_bytes());
+                    }
+                    // BRK's padding byte
+                    (_, None) => bytes.resize(instruction.bytes as usize, 0),
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::disasm::disassemble_range;
+    use super::*;
+
+    fn code(program: &Program) -> Vec<u8> {
+        program.segments()[0].bytes.clone()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        let program = assemble!(
+            "lda #$05",
+            "asl a",
+            "lsr",
+            "sta $10",
+            "sta $10,x",
+            "ldx $10,y",
+            "sta $0200",
+            "sta $0200,x",
+            "sta $0200,y",
+            "sta ($10,x)",
+            "sta ($10),y",
+            "jmp ($0200)",
+            "nop",
+        );
+        assert_eq!(
+            code(&program),
+            [
+                0xa9, 0x05, 0x0a, 0x4a, 0x85, 0x10, 0x95, 0x10, 0xb6, 0x10, 0x8d, 0x00, 0x02, 0x9d,
+                0x00, 0x02, 0x99, 0x00, 0x02, 0x81, 0x10, 0x91, 0x10, 0x6c, 0x00, 0x02, 0xea
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let program = assemble!(
+            "COUNT = 3",
+            "start:  ldx #COUNT",
+            "loop:   dex",
+            "        bne loop",
+            "        jmp done",
+            "        brk",
+            "done:   kil",
+        );
+        assert_eq!(
+            code(&program),
+            [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x0a, 0x80, 0x00, 0x00, 0x02]
+        );
+        assert_eq!(program.symbol("start"), Some(0x8000));
+        assert_eq!(program.symbol("done"), Some(0x800a));
+        assert_eq!(program.symbol("COUNT"), Some(3));
+    }
+
+    #[test]
+    fn test_forward_references_are_absolute() {
+        let program = assemble!("lda later", "later = $10", "lda later");
+        assert_eq!(code(&program), [0xad, 0x10, 0x00, 0xa5, 0x10]);
+    }
+
+    #[test]
+    fn test_directives_and_expressions() {
+        let program = assemble!(
+            "       .org $c000",
+            "table: .byte 1, -1, 'A', \"hi\"",
+            "       .word table, * + 2",
+            "       lda #<table + 1",
+            "       ldx #>(table * 2) & $0f",
+            "       .org $10",
+            "       .byte %1010 | 1 << 4, 10 / 3 ^ ~0",
+        );
+        let segments = program.segments();
+        assert_eq!(segments[0].bytes, []);
+        assert_eq!(segments[1].origin, 0xc000);
+        assert_eq!(
+            segments[1].bytes,
+            [0x01, 0xff, 0x41, 0x68, 0x69, 0x00, 0xc0, 0x07, 0xc0, 0xa9, 0x01, 0xa2, 0x00]
+        );
+        assert_eq!(segments[2].origin, 0x10);
+        assert_eq!(segments[2].bytes, [0x1a, 0xfc]);
+    }
+
+    #[test]
+    fn test_prg_rom_vectors() {
+        let program = assemble!(
+            "reset: jmp reset",
+            "nmi:   rti",
+            "       .org $fffe",
+            "       .word $1234",
+        );
+        let prg_rom = program.prg_rom().unwrap();
+        assert_eq!(prg_rom.len(), 0x8000);
+        assert_eq!(prg_rom[..4], [0x4c, 0x00, 0x80, 0x40]);
+        assert_eq!(prg_rom[0x7ffa..], [0x03, 0x80, 0x00, 0x80, 0x34, 0x12]);
+
+        assert!(assemble!(".org $0200", "nop").prg_rom().is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let errors = [
+            ("nop\nfoo #1", "line 2: unknown instruction foo"),
+            ("lda ($10),x", "line 1: expected Y"),
+            (
+                "stx $0200,x",
+                "line 1: stx does not support that addressing mode",
+            ),
+            (
+                "bne far\n.org $9000\nfar: nop",
+                "line 1: branch to $9000 is out of range",
+            ),
+            ("lda #$100", "line 1: 256 does not fit in a byte"),
+            ("jmp nowhere", "line 1: undefined symbol nowhere"),
+            ("a: nop\na: nop", "line 2: a is already defined"),
+            (
+                "nop\n.org $8000\nnop",
+                "code at $8000 overlaps earlier code",
+            ),
+        ];
+        for (source, error) in errors {
+            assert_eq!(assemble(source), Err(error.to_owned()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_65c02() {
+        assert_eq!(
+            assemble("stz $10"),
+            Err("line 1: unknown instruction stz".to_owned())
+        );
+        let program =
+            assemble!(CpuVariant::Cmos65C02; "stz $10", "lda ($10)", "jmp ($1234,x)", "inc");
+        assert_eq!(
+            code(&program),
+            [0x64, 0x10, 0xb2, 0x10, 0x7c, 0x34, 0x12, 0x1a]
+        );
+    }
+
+    // Every opcode disassembles to text that assembles back to the same instruction
+    #[test]
+    fn test_disassembly_round_trip() {
+        for variant in [CpuVariant::Ricoh2A03, CpuVariant::Cmos65C02] {
+            for op_code in 0..=0xffu8 {
+                let original = &disassemble_range(&[op_code, 0x34, 0x12], 0x8000, variant)[0];
+                let text = original.to_string();
+                let program = assemble_for(&text, variant)
+                    .unwrap_or_else(|error| panic!("{:02x} {}: {}", op_code, text, error));
+                let line = &disassemble_range(&code(&program), 0x8000, variant)[0];
+                assert_eq!(
+                    (
+                        line.instruction.instruction_type,
+                        line.instruction.memory_addressing_mode
+                    ),
+                    (
+                        original.instruction.instruction_type,
+                        original.instruction.memory_addressing_mode
+                    ),
+                    "{:02x} {}",
+                    op_code,
+                    text
+                );
+                assert_eq!(line.operand(), original.operand(), "{:02x}", op_code);
+            }
+        }
+    }
+}
@@ -0,0 +1,552 @@
+use std::ops::RangeInclusive;
+
+use super::{
+    condition::Condition,
+    instructions::{Instruction, Stability},
+    interrupt::InterruptType,
+    CPU,
+};
+use crate::bus::{AddressSpace, Bus, MemoryAccess, MemoryOperation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BreakpointId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    // Stops before the instruction at one of these addresses runs
+    Execute(RangeInclusive<u16>),
+    // Stops before an opcode at least this far off the documented set runs, the usual
+    // sign of code running into data. Stable catches every unofficial opcode.
+    Unofficial(Stability),
+    // Stops after the instruction that made a matching access. An OAM DMA's writes
+    // reach OAM watchpoints, its reads of the source page aren't watched.
+    Watch {
+        space: AddressSpace,
+        range: RangeInclusive<u16>,
+        read: bool,
+        write: bool,
+    },
+    // Stops with the PC on the first instruction of the handler. BRK counts too.
+    Interrupt(InterruptType),
+    // Stops after the instruction during which the PPU got to this dot
+    Position {
+        scanline: u16,
+        dot: u16,
+    },
+}
+
+impl Breakpoint {
+    pub fn execute(address: u16) -> Self {
+        Breakpoint::Execute(address..=address)
+    }
+
+    pub fn read(space: AddressSpace, range: RangeInclusive<u16>) -> Self {
+        Breakpoint::Watch {
+            space,
+            range,
+            read: true,
+            write: false,
+        }
+    }
+
+    pub fn write(space: AddressSpace, range: RangeInclusive<u16>) -> Self {
+        Breakpoint::Watch {
+            space,
+            range,
+            read: false,
+            write: true,
+        }
+    }
+
+    pub fn access(space: AddressSpace, range: RangeInclusive<u16>) -> Self {
+        Breakpoint::Watch {
+            space,
+            range,
+            read: true,
+            write: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakReason {
+    Execute,
+    Watch(MemoryAccess),
+    Interrupt(InterruptType),
+    Position { scanline: u16, dot: u16 },
+}
+
+// What the run loop hands back when it stops on a breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Break {
+    pub id: BreakpointId,
+    pub reason: BreakReason,
+    // The instruction that triggered it, for Execute and Interrupt the one about to run
+    pub program_counter: u16,
+    pub cycle: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    id: BreakpointId,
+    breakpoint: Breakpoint,
+    enabled: bool,
+    // Only stops when this holds, checked every time the breakpoint triggers
+    condition: Option<Condition>,
+    // Times it triggered, whether or not the condition held
+    hits: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    entries: Vec<Entry>,
+    next_id: usize,
+    // Every CPU access goes through watch(), so keep the common case cheap
+    watching_cpu: bool,
+    // Only run_until_break collects hits, step and the other run_* leave them alone
+    armed: bool,
+    // Watchpoints triggered by the instruction being run
+    pending: Vec<(BreakpointId, BreakReason)>,
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.add_with_condition(breakpoint, None)
+    }
+
+    pub fn add_with_condition(
+        &mut self,
+        breakpoint: Breakpoint,
+        condition: Option<Condition>,
+    ) -> BreakpointId {
+        let id = BreakpointId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            breakpoint,
+            enabled: true,
+            condition,
+            hits: 0,
+        });
+        self.update_watching();
+        id
+    }
+
+    pub fn remove(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        let index = self.entries.iter().position(|entry| entry.id == id)?;
+        let entry = self.entries.remove(index);
+        self.update_watching();
+        Some(entry.breakpoint)
+    }
+
+    // Returns false if there is no such breakpoint
+    pub fn set_enabled(&mut self, id: BreakpointId, enabled: bool) -> bool {
+        let found = match self.entry_mut(id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        };
+        self.update_watching();
+        found
+    }
+
+    // Returns false if there is no such breakpoint
+    pub fn set_condition(&mut self, id: BreakpointId, condition: Option<Condition>) -> bool {
+        match self.entry_mut(id) {
+            Some(entry) => {
+                entry.condition = condition;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn condition(&self, id: BreakpointId) -> Option<&Condition> {
+        self.entry(id)?.condition.as_ref()
+    }
+
+    pub fn hits(&self, id: BreakpointId) -> Option<u64> {
+        self.entry(id).map(|entry| entry.hits)
+    }
+
+    pub fn reset_hits(&mut self) {
+        for entry in &mut self.entries {
+            entry.hits = 0;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.update_watching();
+    }
+
+    pub fn get(&self, id: BreakpointId) -> Option<&Breakpoint> {
+        self.entry(id).map(|entry| &entry.breakpoint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.id, &entry.breakpoint))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry(&self, id: BreakpointId) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    fn entry_mut(&mut self, id: BreakpointId) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| entry.enabled)
+    }
+
+    fn update_watching(&mut self) {
+        self.watching_cpu = self.armed && self.watches(AddressSpace::Cpu);
+    }
+
+    pub(crate) fn arm(&mut self, armed: bool) {
+        self.armed = armed;
+        self.pending.clear();
+        self.update_watching();
+    }
+
+    pub(crate) fn watches(&self, watched: AddressSpace) -> bool {
+        self.enabled().any(
+            |entry| matches!(entry.breakpoint, Breakpoint::Watch { space, .. } if space == watched),
+        )
+    }
+
+    pub(crate) fn execute_hits(
+        &self,
+        address: u16,
+        instruction: &Instruction,
+    ) -> Vec<(BreakpointId, BreakReason)> {
+        self.enabled()
+            .filter(|entry| match &entry.breakpoint {
+                Breakpoint::Execute(range) => range.contains(&address),
+                Breakpoint::Unofficial(stability) => instruction.stability >= *stability,
+                _ => false,
+            })
+            .map(|entry| (entry.id, BreakReason::Execute))
+            .collect()
+    }
+
+    pub(crate) fn interrupt_hits(
+        &self,
+        interrupt: InterruptType,
+    ) -> Vec<(BreakpointId, BreakReason)> {
+        self.enabled()
+            .filter(|entry| entry.breakpoint == Breakpoint::Interrupt(interrupt))
+            .map(|entry| (entry.id, BreakReason::Interrupt(interrupt)))
+            .collect()
+    }
+
+    // The Position breakpoints passed going from one (scanline, dot) to the other
+    pub(crate) fn position_hits(
+        &self,
+        from: (u16, u16),
+        to: (u16, u16),
+    ) -> Vec<(BreakpointId, BreakReason)> {
+        let linear = |(scanline, dot): (u16, u16)| scanline as u32 * 341 + dot as u32;
+        let (from, to) = (linear(from), linear(to));
+        self.enabled()
+            .filter_map(|entry| match entry.breakpoint {
+                Breakpoint::Position { scanline, dot } => {
+                    let target = linear((scanline, dot));
+                    // The position wraps around at the end of the frame
+                    let passed = if from <= to {
+                        from < target && target <= to
+                    } else {
+                        from < target || target <= to
+                    };
+                    passed.then_some((entry.id, BreakReason::Position { scanline, dot }))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[inline]
+    pub(crate) fn watch_cpu(&mut self, operation: MemoryOperation, address: u16, value: u8) {
+        if self.watching_cpu {
+            self.watch(MemoryAccess {
+                space: AddressSpace::Cpu,
+                operation,
+                address,
+                value,
+            });
+        }
+    }
+
+    pub(crate) fn watch(&mut self, access: MemoryAccess) {
+        let hits: Vec<_> = self
+            .enabled()
+            .filter_map(|entry| match &entry.breakpoint {
+                Breakpoint::Watch {
+                    space,
+                    range,
+                    read,
+                    write,
+                } if *space == access.space && range.contains(&access.address) => {
+                    let matches = match access.operation {
+                        MemoryOperation::Read => *read,
+                        MemoryOperation::Write => *write,
+                    };
+                    matches.then_some((entry.id, BreakReason::Watch(access)))
+                }
+                _ => None,
+            })
+            .collect();
+        self.pending.extend(hits);
+    }
+
+    pub(crate) fn take_hits(&mut self) -> Vec<(BreakpointId, BreakReason)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    // Counts the hit and says whether the breakpoint's condition holds
+    pub(crate) fn trigger<B: Bus>(
+        cpu: &mut CPU<B>,
+        id: BreakpointId,
+        reason: &BreakReason,
+    ) -> bool {
+        let hits = match cpu.breakpoints.entry_mut(id) {
+            Some(entry) => {
+                entry.hits += 1;
+                entry.hits
+            }
+            None => return false,
+        };
+        let access = match reason {
+            BreakReason::Watch(access) => Some(access),
+            _ => None,
+        };
+        match cpu.breakpoints.condition(id) {
+            Some(condition) => condition.is_met(cpu, hits, access),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::CPU;
+    use super::*;
+    use crate::assemble;
+    use crate::bus::{Bus, MemoryBus};
+
+    #[test]
+    fn test_execute_breakpoint() {
+        let mut cpu = assemble!("loop: inx", "iny", "jmp loop").cpu();
+        let id = cpu.breakpoints.add(Breakpoint::execute(0x8001));
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.reason, BreakReason::Execute);
+        assert_eq!(hit.program_counter, 0x8001);
+        assert_eq!((cpu.x, cpu.y), (1, 0));
+
+        // Resuming runs the instruction it stopped on
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.program_counter, 0x8001);
+        assert_eq!((cpu.x, cpu.y), (2, 1));
+
+        cpu.breakpoints.set_enabled(id, false);
+        assert_eq!(cpu.run_until_break(|cpu| cpu.x == 5), None);
+
+        // Only an Execute stop is skipped on resume, not one on the instruction after a watchpoint
+        let mut cpu = assemble!("sta $10", "inx", "kil").cpu();
+        let write = cpu
+            .breakpoints
+            .add(Breakpoint::write(AddressSpace::Cpu, 0x10..=0x10));
+        let execute = cpu.breakpoints.add(Breakpoint::execute(0x8002));
+        assert_eq!(cpu.run_until_break(|_| false).unwrap().id, write);
+        assert_eq!(cpu.program_counter, 0x8002);
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!((hit.id, hit.reason), (execute, BreakReason::Execute));
+        assert_eq!(cpu.x, 0);
+    }
+
+    #[test]
+    fn test_unofficial_breakpoint() {
+        let mut cpu = assemble!("lax $10", "xaa #$00", "nop", "kil").cpu();
+        let unstable = cpu
+            .breakpoints
+            .add(Breakpoint::Unofficial(Stability::Unstable));
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!((hit.id, hit.program_counter), (unstable, 0x8002));
+
+        let unofficial = cpu
+            .breakpoints
+            .add(Breakpoint::Unofficial(Stability::Stable));
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!((hit.id, hit.program_counter), (unofficial, 0x8005));
+    }
+
+    #[test]
+    fn test_cpu_watchpoints() {
+        let mut cpu = assemble!("lda $8000", "ldx #$07", "stx $0210", "kil").cpu();
+        // Fetching the program doesn't count as reading it
+        cpu.breakpoints
+            .add(Breakpoint::read(AddressSpace::Cpu, 0x8003..=0x8004));
+        let write = cpu
+            .breakpoints
+            .add(Breakpoint::write(AddressSpace::Cpu, 0x0200..=0x02ff));
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, write);
+        assert_eq!(hit.program_counter, 0x8005);
+        assert_eq!(
+            hit.reason,
+            BreakReason::Watch(MemoryAccess {
+                space: AddressSpace::Cpu,
+                operation: MemoryOperation::Write,
+                address: 0x0210,
+                value: 0x07,
+            })
+        );
+        assert_eq!(cpu.program_counter, 0x8008);
+
+        assert!(cpu.breakpoints.remove(write).is_some());
+        assert_eq!(cpu.breakpoints.remove(write), None);
+        assert_eq!(cpu.run_until_break(|_| false), None);
+        assert!(cpu.halted);
+    }
+
+    #[test]
+    fn test_watchpoints_outside_a_run() {
+        let mut cpu = assemble!("sta $0200", "nop", "kil").cpu();
+        cpu.breakpoints
+            .add(Breakpoint::write(AddressSpace::Cpu, 0x0200..=0x0200));
+
+        // Nothing is kept for a run_until_break that comes later
+        cpu.step();
+        assert!(cpu.breakpoints.pending.is_empty());
+        assert_eq!(cpu.run_until_break(|_| false), None);
+        assert!(cpu.halted);
+    }
+
+    #[test]
+    fn test_interrupt_breakpoint() {
+        let mut cpu = assemble!("nop", "nop", ".org $9000", "kil").cpu();
+        cpu.bus.write_word(0xfffe, 0x9000);
+        cpu.processor_status.set_interrupt_disable(false);
+        cpu.bus.set_irq(true);
+        let id = cpu
+            .breakpoints
+            .add(Breakpoint::Interrupt(InterruptType::IRQ));
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.reason, BreakReason::Interrupt(InterruptType::IRQ));
+        assert_eq!(hit.program_counter, 0x9000);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(!cpu.halted);
+
+        // Execute breakpoints on the handler's first instruction stop there too
+        let mut cpu = assemble!("nop", "nop", ".org $9000", "inx", "kil").cpu();
+        cpu.bus.write_word(0xfffe, 0x9000);
+        cpu.processor_status.set_interrupt_disable(false);
+        cpu.bus.set_irq(true);
+        let id = cpu.breakpoints.add(Breakpoint::execute(0x9000));
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!((hit.id, hit.reason), (id, BreakReason::Execute));
+        assert_eq!((hit.program_counter, cpu.x), (0x9000, 0));
+        assert_eq!(cpu.run_until_break(|_| false), None);
+        assert_eq!(cpu.x, 1);
+    }
+
+    #[test]
+    fn test_ppu_and_oam_watchpoints() {
+        let program = assemble!(
+            "      lda #$21",
+            "      sta $2006",
+            "      lda #$08",
+            "      sta $2006",
+            "      sta $2007",
+            "      lda #$10",
+            "      sta $2003",
+            "      sta $2004",
+            "loop: jmp loop",
+        );
+        let mut cpu = CPU::new(MemoryBus::new(program.rom().unwrap()));
+        cpu.breakpoints
+            .add(Breakpoint::write(AddressSpace::Ppu, 0x2000..=0x23ff));
+        cpu.breakpoints
+            .add(Breakpoint::write(AddressSpace::Oam, 0x10..=0x10));
+        let position = cpu.breakpoints.add(Breakpoint::Position {
+            scanline: 10,
+            dot: 100,
+        });
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.program_counter, 0x800a);
+        assert_eq!(
+            hit.reason,
+            BreakReason::Watch(MemoryAccess {
+                space: AddressSpace::Ppu,
+                operation: MemoryOperation::Write,
+                address: 0x2108,
+                value: 0x08,
+            })
+        );
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.program_counter, 0x8012);
+        assert!(matches!(
+            hit.reason,
+            BreakReason::Watch(MemoryAccess {
+                space: AddressSpace::Oam,
+                address: 0x10,
+                value: 0x10,
+                ..
+            })
+        ));
+
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, position);
+        let (scanline, dot) = cpu.bus.ppu_position().unwrap();
+        assert_eq!(scanline, 10);
+        assert!((100..100 + 3 * 7).contains(&dot));
+    }
+
+    #[test]
+    fn test_conditions_and_hits() {
+        let mut cpu = assemble!("loop: inx", "stx $10", "jmp loop").cpu();
+        let x = cpu.breakpoints.add_with_condition(
+            Breakpoint::execute(0x8001),
+            Some(Condition::parse("x == 3").unwrap()),
+        );
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, x);
+        assert_eq!(cpu.x, 3);
+        // Hits count every time it triggered, not just when it stopped
+        assert_eq!(cpu.breakpoints.hits(x), Some(3));
+        assert_eq!(cpu.breakpoints.condition(x).unwrap().to_string(), "x == 3");
+
+        assert!(cpu
+            .breakpoints
+            .set_condition(x, Some(Condition::parse("hits == 5").unwrap())));
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, x);
+        assert_eq!(cpu.x, 5);
+        cpu.breakpoints.remove(x);
+
+        let write = cpu.breakpoints.add_with_condition(
+            Breakpoint::write(AddressSpace::Cpu, 0x10..=0x10),
+            Some(Condition::parse("value == $ff && address == $10").unwrap()),
+        );
+        let hit = cpu.run_until_break(|_| false).unwrap();
+        assert_eq!(hit.id, write);
+        assert_eq!(cpu.bus.peek(0x10), 0xff);
+        assert_eq!(cpu.breakpoints.hits(write), Some(0xff - 5 + 1));
+    }
+}
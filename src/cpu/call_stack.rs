@@ -0,0 +1,315 @@
+/*
+A shadow of the 6502 stack that only holds the calls: JSR and interrupts push a
+frame, RTS and RTI pop it. Games don't always play by those rules (jump tables
+that push an address and RTS to it, routines that pull their own return address
+and bail out to their caller's caller, TXS resetting the stack), so each frame
+remembers where on the real stack its return address lives:
+- a frame whose bytes have been pulled off the real stack is dropped
+- a return that doesn't land where the top frame said it would leaves the shadow
+  stack alone
+Both are recorded as an Imbalance.
+*/
+use std::fmt::Display;
+
+use super::interrupt::InterruptType;
+
+// The real stack can't hold more than 128 return addresses, anything past that
+// is runaway recursion through a TXS
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    Subroutine,
+    Interrupt(InterruptType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: FrameKind,
+    // The JSR or BRK, or the instruction an NMI/IRQ cut in before
+    pub caller: u16,
+    pub target: u16,
+    // Where RTS/RTI should go back to
+    pub return_address: u16,
+    // The stack pointer before the return address was pushed, and after it is pulled
+    pub stack_pointer: u8,
+    pub frame: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImbalanceKind {
+    // The stack pointer moved past these frames without returning from them
+    Abandoned { frames: usize },
+    // An RTS/RTI that no frame expected, like an RTS jump table
+    UnmatchedReturn { return_address: u16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Imbalance {
+    pub kind: ImbalanceKind,
+    // The instruction that gave it away
+    pub program_counter: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    last_imbalance: Option<Imbalance>,
+    imbalances: u64,
+}
+
+impl CallStack {
+    // Innermost call last
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn last_imbalance(&self) -> Option<Imbalance> {
+        self.last_imbalance
+    }
+
+    pub fn imbalances(&self) -> u64 {
+        self.imbalances
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.last_imbalance = None;
+        self.imbalances = 0;
+    }
+
+    pub(crate) fn push(&mut self, frame: CallFrame) {
+        self.abandon(frame.stack_pointer, true, frame.caller);
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // Called with the stack pointer after the return address (and status) were pulled
+    pub(crate) fn pop(&mut self, program_counter: u16, return_address: u16, stack_pointer: u8) {
+        self.abandon(stack_pointer, false, program_counter);
+        match self.frames.last() {
+            Some(frame)
+                if frame.return_address == return_address
+                    && frame.stack_pointer == stack_pointer =>
+            {
+                self.frames.pop();
+            }
+            _ => self.record(
+                ImbalanceKind::UnmatchedReturn { return_address },
+                program_counter,
+            ),
+        }
+    }
+
+    // Drops the frames whose return address is no longer on the stack. A push
+    // overwrites the frames at the same stack pointer, a pull only the ones below it.
+    fn abandon(&mut self, stack_pointer: u8, pushing: bool, program_counter: u16) {
+        let live = self
+            .frames
+            .iter()
+            .rposition(|frame| {
+                if pushing {
+                    frame.stack_pointer > stack_pointer
+                } else {
+                    frame.stack_pointer >= stack_pointer
+                }
+            })
+            .map_or(0, |index| index + 1);
+        let frames = self.frames.len() - live;
+        if frames > 0 {
+            self.frames.truncate(live);
+            self.record(ImbalanceKind::Abandoned { frames }, program_counter);
+        }
+    }
+
+    fn record(&mut self, kind: ImbalanceKind, program_counter: u16) {
+        self.last_imbalance = Some(Imbalance {
+            kind,
+            program_counter,
+        });
+        self.imbalances += 1;
+    }
+}
+
+impl Display for CallFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            FrameKind::Subroutine => "JSR".to_owned(),
+            FrameKind::Interrupt(interrupt) => format!("{:?}", interrupt),
+        };
+        write!(
+            f,
+            "${:04X} from ${:04X} ({}, returns to ${:04X}, SP ${:02X}, frame {})",
+            self.target, self.caller, kind, self.return_address, self.stack_pointer, self.frame
+        )
+    }
+}
+
+impl Display for Imbalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ImbalanceKind::Abandoned { frames } => write!(
+                f,
+                "${:04X} dropped {} frame(s) without returning",
+                self.program_counter, frames
+            ),
+            ImbalanceKind::UnmatchedReturn { return_address } => write!(
+                f,
+                "${:04X} returned to ${:04X} outside of any call",
+                self.program_counter, return_address
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::CPU;
+    use super::*;
+    use crate::assemble;
+    use crate::bus::flat_ram::FlatRam;
+
+    fn run(program: crate::cpu::asm::Program, until: u16) -> CPU<FlatRam> {
+        let mut cpu = program.cpu();
+        cpu.run_until(|cpu| cpu.program_counter == until || cpu.halted);
+        cpu
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let program = assemble!(
+            "       jsr outer",
+            "done:  kil",
+            "outer: jsr inner",
+            "       rts",
+            "inner: nop",
+            "here:  rts",
+        );
+        let here = program.symbol("here").unwrap();
+        let cpu = run(program, here);
+
+        let frames = cpu.call_stack.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0],
+            CallFrame {
+                kind: FrameKind::Subroutine,
+                caller: 0x8000,
+                target: 0x8004,
+                return_address: 0x8003,
+                stack_pointer: 0xfd,
+                frame: 0,
+            }
+        );
+        assert_eq!((frames[1].caller, frames[1].target), (0x8004, 0x8008));
+        assert_eq!(
+            cpu.backtrace(),
+            [
+                "#0 $8009",
+                "#1 $8008 from $8004 (JSR, returns to $8007, SP $FB, frame 0)",
+                "#2 $8004 from $8000 (JSR, returns to $8003, SP $FD, frame 0)",
+            ]
+            .join("\n")
+        );
+
+        let mut cpu = cpu;
+        cpu.run_until(|cpu| cpu.halted);
+        assert_eq!(cpu.call_stack.depth(), 0);
+        assert_eq!(cpu.call_stack.imbalances(), 0);
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let program = assemble!("       brk", "       nop", "       kil",);
+        let mut cpu = program.cpu();
+        cpu.bus.write_word(0xfffe, 0x8003);
+        cpu.step();
+
+        let frame = cpu.call_stack.frames()[0];
+        assert_eq!(frame.kind, FrameKind::Interrupt(InterruptType::BRK));
+        assert_eq!(
+            (frame.caller, frame.target, frame.return_address),
+            (0x8000, 0x8003, 0x8002)
+        );
+    }
+
+    #[test]
+    fn test_rts_jump_table() {
+        // Pushes the target - 1 and returns to it from inside a subroutine
+        let program = assemble!(
+            "        jsr dispatch",
+            "        kil",
+            "dispatch:",
+            "        lda #>(target - 1)",
+            "        pha",
+            "        lda #<(target - 1)",
+            "        pha",
+            "        rts",
+            "target: nop",
+            "        rts",
+        );
+        let target = program.symbol("target").unwrap();
+        let mut cpu = run(program, target);
+        assert_eq!(cpu.call_stack.depth(), 1);
+        assert_eq!(
+            cpu.call_stack.last_imbalance(),
+            Some(Imbalance {
+                kind: ImbalanceKind::UnmatchedReturn {
+                    return_address: target
+                },
+                program_counter: 0x800a,
+            })
+        );
+
+        // The subroutine's own RTS still matches up
+        cpu.run_until(|cpu| cpu.halted);
+        assert_eq!(cpu.call_stack.depth(), 0);
+        assert_eq!(cpu.call_stack.imbalances(), 1);
+    }
+
+    #[test]
+    fn test_abandoned_frames() {
+        // inner pulls its return address and goes straight back to main
+        let program = assemble!(
+            "       jsr outer",
+            "       kil",
+            "outer: jsr inner",
+            "       brk",
+            "inner: pla",
+            "       pla",
+            "       rts",
+        );
+        let mut cpu = run(program, 0);
+        assert!(cpu.halted);
+        assert_eq!(cpu.call_stack.depth(), 0);
+        assert_eq!(
+            cpu.call_stack
+                .last_imbalance()
+                .map(|imbalance| imbalance.kind),
+            Some(ImbalanceKind::Abandoned { frames: 1 })
+        );
+
+        // A TXS drops everything above the new stack pointer on the next call
+        let program = assemble!(
+            "       jsr reset",
+            "reset: ldx #$ff",
+            "       txs",
+            "       jsr done",
+            "done:  kil",
+        );
+        cpu = run(program, 0);
+        assert_eq!(cpu.call_stack.depth(), 1);
+        assert_eq!(
+            cpu.call_stack
+                .last_imbalance()
+                .map(|imbalance| imbalance.kind),
+            Some(ImbalanceKind::Abandoned { frames: 1 })
+        );
+    }
+}
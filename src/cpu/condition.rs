@@ -0,0 +1,289 @@
+/*
+Breakpoint conditions, parsed once and evaluated every time the breakpoint triggers:
+
+    A == $10 && [$0300] > 5 && frame > 100
+    {$10} == $c000 || hits % 10 == 0
+
+Values are integers, comparisons and the boolean operators give 1 or 0 and anything
+other than 0 counts as true. Division by zero gives 0.
+
+    registers   a x y sp pc p
+    flags       c z i d v n
+    memory      [address] reads a byte, {address} a little endian word
+    ppu         scanline dot frame (0 without a PPU)
+    other       cycle, hits (times this breakpoint triggered, this one included),
+                value and address (of the access that set off a watchpoint)
+
+Operators from loosest to tightest: || && | ^ & (== !=) (< <= > >=) (<< >>) (+ -)
+(* / %) and the unary - ! ~. Numbers are $hex, %binary, decimal or 'c', parsed
+the same way as in the assembler (see expr.rs).
+*/
+use std::{fmt::Display, str::FromStr};
+
+use super::{
+    expr::{self, expected_value, Parser, Syntax, Token},
+    processor_status::ProcessorStatus,
+    CPU,
+};
+use crate::bus::{Bus, MemoryAccess};
+
+struct ConditionSyntax;
+
+impl Syntax for ConditionSyntax {
+    type Term = Term;
+    const PUNCTUATION: &'static [&'static str] = &[
+        "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "+", "-", "*", "/", "%", "&",
+        "|", "^", "!", "~", "(", ")", "[", "]", "{", "}",
+    ];
+    const BINARY_OPERATORS: &'static [&'static [&'static str]] = &[
+        &["||"],
+        &["&&"],
+        &["|"],
+        &["^"],
+        &["&"],
+        &["==", "!="],
+        &["<", "<=", ">", ">="],
+        &["<<", ">>"],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+    const UNARY_OPERATORS: &'static [&'static str] = &["-", "!", "~"];
+    const VALUE: &'static str = "a value";
+
+    fn term(parser: &mut Parser<Self>, token: Token) -> Result<Expr, String> {
+        let (close, term): (_, fn(Box<Expr>) -> Term) = match token {
+            Token::Name(name) => {
+                return Variable::from_name(&name)
+                    .map(|variable| Expr::Term(Term::Variable(variable)))
+                    .ok_or_else(|| format!("unknown variable {}", name))
+            }
+            Token::Punct("[") => ("]", Term::Byte),
+            Token::Punct("{") => ("}", Term::Word),
+            token => return Err(expected_value::<Self>(&token)),
+        };
+        let address = parser.expression()?;
+        parser.expect(close)?;
+        Ok(Expr::Term(term(Box::new(address))))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    StackPointer,
+    ProgramCounter,
+    Status,
+    Flag(ProcessorStatus),
+    Scanline,
+    Dot,
+    Frame,
+    Cycle,
+    Hits,
+    Value,
+    Address,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => Variable::A,
+            "x" => Variable::X,
+            "y" => Variable::Y,
+            "sp" | "s" => Variable::StackPointer,
+            "pc" => Variable::ProgramCounter,
+            "p" => Variable::Status,
+            "c" => Variable::Flag(ProcessorStatus::CARRY),
+            "z" => Variable::Flag(ProcessorStatus::ZERO),
+            "i" => Variable::Flag(ProcessorStatus::INTERRUPT_DISABLE),
+            "d" => Variable::Flag(ProcessorStatus::DECIMAL),
+            "v" => Variable::Flag(ProcessorStatus::OVERFLOW),
+            "n" => Variable::Flag(ProcessorStatus::NEGATIVE),
+            "scanline" => Variable::Scanline,
+            "dot" => Variable::Dot,
+            "frame" => Variable::Frame,
+            "cycle" => Variable::Cycle,
+            "hits" => Variable::Hits,
+            "value" => Variable::Value,
+            "address" => Variable::Address,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Variable(Variable),
+    Byte(Box<Expr>),
+    Word(Box<Expr>),
+}
+
+type Expr = expr::Expr<Term>;
+
+// Everything a condition can look at
+struct Context<'a, B> {
+    cpu: &'a CPU<B>,
+    hits: u64,
+    access: Option<&'a MemoryAccess>,
+}
+
+impl Expr {
+    fn eval<B: Bus>(&self, context: &Context<B>) -> i64 {
+        let cpu = context.cpu;
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Term(Term::Variable(variable)) => match *variable {
+                Variable::A => cpu.a as i64,
+                Variable::X => cpu.x as i64,
+                Variable::Y => cpu.y as i64,
+                Variable::StackPointer => cpu.stack_pointer as i64,
+                Variable::ProgramCounter => cpu.program_counter as i64,
+                Variable::Status => cpu.processor_status.bits() as i64,
+                Variable::Flag(flag) => cpu.processor_status.contains(flag) as i64,
+                Variable::Scanline => cpu
+                    .bus
+                    .ppu_position()
+                    .map_or(0, |(scanline, _)| scanline as i64),
+                Variable::Dot => cpu.bus.ppu_position().map_or(0, |(_, dot)| dot as i64),
+                Variable::Frame => cpu.bus.ppu_frame().unwrap_or(0) as i64,
+                Variable::Cycle => cpu.cycle as i64,
+                Variable::Hits => context.hits as i64,
+                Variable::Value => context.access.map_or(0, |access| access.value as i64),
+                Variable::Address => context.access.map_or(0, |access| access.address as i64),
+            },
+            Expr::Term(Term::Byte(address)) => cpu.bus.peek(address.eval(context) as u16) as i64,
+            Expr::Term(Term::Word(address)) => {
+                let address = address.eval(context) as u16;
+                let lo = cpu.bus.peek(address) as i64;
+                let hi = cpu.bus.peek(address.wrapping_add(1)) as i64;
+                hi << 8 | lo
+            }
+            Expr::Unary(operator, operand) => {
+                let value = operand.eval(context);
+                match *operator {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    _ => unreachable!("unknown unary operator {}", operator),
+                }
+            }
+            // Short circuit, so {$10} == 0 || [{$10}] == 1 never reads through a null pointer
+            Expr::Binary("&&", left, right) => {
+                (left.eval(context) != 0 && right.eval(context) != 0) as i64
+            }
+            Expr::Binary("||", left, right) => {
+                (left.eval(context) != 0 || right.eval(context) != 0) as i64
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = left.eval(context);
+                let right = right.eval(context);
+                match *operator {
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left.checked_div(right).unwrap_or(0),
+                    "%" => left.checked_rem(right).unwrap_or(0),
+                    "&" => left & right,
+                    "|" => left | right,
+                    "^" => left ^ right,
+                    "<<" => left.checked_shl(right as u32).unwrap_or(0),
+                    ">>" => left.checked_shr(right as u32).unwrap_or(0),
+                    "==" => (left == right) as i64,
+                    "!=" => (left != right) as i64,
+                    "<" => (left < right) as i64,
+                    "<=" => (left <= right) as i64,
+                    ">" => (left > right) as i64,
+                    ">=" => (left >= right) as i64,
+                    _ => unreachable!("unknown binary operator {}", operator),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser::<ConditionSyntax>::new(source)?;
+        let expr = parser.expression()?;
+        parser.expect_end()?;
+        Ok(Self {
+            source: source.trim().to_owned(),
+            expr,
+        })
+    }
+
+    pub fn eval<B: Bus>(&self, cpu: &CPU<B>, hits: u64, access: Option<&MemoryAccess>) -> i64 {
+        self.expr.eval(&Context { cpu, hits, access })
+    }
+
+    pub fn is_met<B: Bus>(&self, cpu: &CPU<B>, hits: u64, access: Option<&MemoryAccess>) -> bool {
+        self.eval(cpu, hits, access) != 0
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::flat_ram::FlatRam;
+
+    fn eval(cpu: &CPU<FlatRam>, source: &str) -> i64 {
+        Condition::parse(source).unwrap().eval(cpu, 3, None)
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut bus = FlatRam::new();
+        bus.load(0x0300, &[0x06]);
+        bus.load(0x0010, &[0x00, 0xc0]);
+        let mut cpu = CPU::new(bus);
+        cpu.a = 0x10;
+        cpu.processor_status = ProcessorStatus::CARRY;
+
+        assert_eq!(eval(&cpu, "A == $10 && [$0300] > 5 && frame > 100"), 0);
+        assert_eq!(eval(&cpu, "a == $10 && [$0300] > 5 && frame == 0"), 1);
+        assert_eq!(eval(&cpu, "{$10}"), 0xc000);
+        assert_eq!(eval(&cpu, "[{$10} - $bd00]"), 0x06);
+        assert_eq!(eval(&cpu, "1 + 2 * 3 - -4 % 3"), 8);
+        assert_eq!(eval(&cpu, "%1010 | 1 << 4"), 0x1a);
+        assert_eq!(eval(&cpu, "hits % 3 == 0 && c && !z"), 1);
+        assert_eq!(eval(&cpu, "(1 + 2) * 3 >= 9 == 1"), 1);
+        assert_eq!(eval(&cpu, "7 / 0"), 0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Condition::parse("foo == 1"),
+            Err("unknown variable foo".to_owned())
+        );
+        assert_eq!(Condition::parse("[$10"), Err("expected ]".to_owned()));
+        assert_eq!(Condition::parse("a =="), Err("expected a value".to_owned()));
+        assert_eq!(
+            Condition::parse("a = 1"),
+            Err("unexpected character '='".to_owned())
+        );
+        assert!(Condition::parse("a 1").is_err());
+        assert_eq!("x >= 3".parse::<Condition>().unwrap().to_string(), "x >= 3");
+    }
+}
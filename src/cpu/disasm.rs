@@ -0,0 +1,329 @@
+use std::fmt::Display;
+
+use super::{
+    instructions::{Access, Instruction, MemoryAdressingMode},
+    variant::CpuVariant,
+    CPU,
+};
+use crate::bus::{cdl::PrgUsage, Bus};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: &'static Instruction,
+}
+
+// Decodes the instruction at address. Memory is only ever peeked so this is safe
+// to call on a live bus (PPU registers and the like are left alone).
+pub fn disassemble<B: Bus>(bus: &B, address: u16, variant: CpuVariant) -> Disassembly {
+    let instruction = &variant.instruction_set()[bus.peek(address) as usize];
+    let bytes = (0..instruction.bytes as u16)
+        .map(|offset| bus.peek(address.wrapping_add(offset)))
+        .collect();
+    Disassembly {
+        address,
+        bytes,
+        instruction,
+    }
+}
+
+// Decodes a block of code such as PRG ROM mapped at origin. An instruction cut
+// off by the end of the block is left out.
+pub fn disassemble_range(code: &[u8], origin: u16, variant: CpuVariant) -> Vec<Disassembly> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = &variant.instruction_set()[code[offset] as usize];
+        let end = offset + instruction.bytes as usize;
+        if end > code.len() {
+            break;
+        }
+        lines.push(Disassembly {
+            address: origin.wrapping_add(offset as u16),
+            bytes: code[offset..end].to_vec(),
+            instruction,
+        });
+        offset = end;
+    }
+    lines
+}
+
+// A line of a listing made with the help of a code/data log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listing {
+    Code(Disassembly),
+    Data { address: u16, bytes: Vec<u8> },
+}
+
+// Like disassemble_range but only decodes what the log saw run, everything else
+// comes out as .byte lines of up to 8 bytes. usage lines up with code.
+pub fn disassemble_logged(
+    code: &[u8],
+    usage: &[PrgUsage],
+    origin: u16,
+    variant: CpuVariant,
+) -> Vec<Listing> {
+    let is_code = |offset: usize| {
+        usage
+            .get(offset)
+            .is_some_and(|usage| usage.contains(PrgUsage::CODE))
+    };
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < code.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let instruction = &variant.instruction_set()[code[offset] as usize];
+        let end = offset + instruction.bytes as usize;
+        if is_code(offset) && end <= code.len() {
+            lines.push(Listing::Code(Disassembly {
+                address,
+                bytes: code[offset..end].to_vec(),
+                instruction,
+            }));
+            offset = end;
+            continue;
+        }
+
+        let mut end = offset + 1;
+        while end < code.len() && end - offset < 8 && !is_code(end) {
+            end += 1;
+        }
+        lines.push(Listing::Data {
+            address,
+            bytes: code[offset..end].to_vec(),
+        });
+        offset = end;
+    }
+    lines
+}
+
+impl Listing {
+    pub fn address(&self) -> u16 {
+        match self {
+            Listing::Code(line) => line.address,
+            Listing::Data { address, .. } => *address,
+        }
+    }
+}
+
+// Data is written the way the assembler reads it back
+impl Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listing::Code(line) => write!(f, "{}", line),
+            Listing::Data { bytes, .. } => {
+                let bytes: Vec<String> =
+                    bytes.iter().map(|byte| format!("${:02X}", byte)).collect();
+                write!(f, ".byte {}", bytes.join(", "))
+            }
+        }
+    }
+}
+
+impl Disassembly {
+    pub fn mnemonic(&self) -> &'static str {
+        self.instruction.mnemonic
+    }
+
+    fn byte_operand(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    fn word_operand(&self) -> u16 {
+        (self.bytes[2] as u16) << 8 | self.bytes[1] as u16
+    }
+
+    // Where a relative branch lands when taken
+    pub fn branch_target(&self) -> u16 {
+        self.address
+            .wrapping_add(2)
+            .wrapping_add(self.byte_operand() as i8 as u16)
+    }
+
+    pub fn operand(&self) -> String {
+        match self.instruction.memory_addressing_mode {
+            MemoryAdressingMode::Implied => String::new(),
+            MemoryAdressingMode::Accumulator => "A".to_owned(),
+            MemoryAdressingMode::Immediate => format!("#${:02X}", self.byte_operand()),
+            MemoryAdressingMode::ZeroPage => format!("${:02X}", self.byte_operand()),
+            MemoryAdressingMode::ZeroPageX => format!("${:02X},X", self.byte_operand()),
+            MemoryAdressingMode::ZeroPageY => format!("${:02X},Y", self.byte_operand()),
+            MemoryAdressingMode::ZeroPageIndirect => format!("(${:02X})", self.byte_operand()),
+            MemoryAdressingMode::IndirectX => format!("(${:02X},X)", self.byte_operand()),
+            MemoryAdressingMode::IndirectY => format!("(${:02X}),Y", self.byte_operand()),
+            MemoryAdressingMode::Absolute => format!("${:04X}", self.word_operand()),
+            MemoryAdressingMode::AbsoluteX => format!("${:04X},X", self.word_operand()),
+            MemoryAdressingMode::AbsoluteY => format!("${:04X},Y", self.word_operand()),
+            MemoryAdressingMode::Indirect => format!("(${:04X})", self.word_operand()),
+            MemoryAdressingMode::AbsoluteIndirectX => format!("(${:04X},X)", self.word_operand()),
+            MemoryAdressingMode::Relative => format!("${:04X}", self.branch_target()),
+        }
+    }
+
+    /*
+    The operand followed by the effective address and the value currently there,
+    in the same format as nestest.log:
+        STA $33,X @ 33 = 00
+        LDA ($89),Y = 0300 @ 0300 = 89
+        JMP ($0200) = DB7E
+    The registers and memory of the CPU are read as they are right now, so call this
+    before the instruction runs.
+    */
+    pub fn annotated<B: Bus>(&self, cpu: &CPU<B>) -> String {
+        let bus = &cpu.bus;
+        let peek_word_zero_page = |address: u8| {
+            (bus.peek(address.wrapping_add(1) as u16) as u16) << 8 | bus.peek(address as u16) as u16
+        };
+
+        let annotation = match self.instruction.memory_addressing_mode {
+            MemoryAdressingMode::ZeroPage => {
+                format!(" = {:02X}", bus.peek(self.byte_operand() as u16))
+            }
+            MemoryAdressingMode::ZeroPageX | MemoryAdressingMode::ZeroPageY => {
+                let index = match self.instruction.memory_addressing_mode {
+                    MemoryAdressingMode::ZeroPageX => cpu.x,
+                    _ => cpu.y,
+                };
+                let address = self.byte_operand().wrapping_add(index);
+                format!(" @ {:02X} = {:02X}", address, bus.peek(address as u16))
+            }
+            // JMP and JSR go to the address rather than touch what's there
+            MemoryAdressingMode::Absolute if self.instruction.access == Access::Jump => {
+                String::new()
+            }
+            MemoryAdressingMode::Absolute => {
+                format!(" = {:02X}", bus.peek(self.word_operand()))
+            }
+            MemoryAdressingMode::AbsoluteX | MemoryAdressingMode::AbsoluteY => {
+                let index = match self.instruction.memory_addressing_mode {
+                    MemoryAdressingMode::AbsoluteX => cpu.x,
+                    _ => cpu.y,
+                };
+                let address = self.word_operand().wrapping_add(index as u16);
+                format!(" @ {:04X} = {:02X}", address, bus.peek(address))
+            }
+            MemoryAdressingMode::IndirectX => {
+                let pointer = self.byte_operand().wrapping_add(cpu.x);
+                let address = peek_word_zero_page(pointer);
+                format!(
+                    " @ {:02X} = {:04X} = {:02X}",
+                    pointer,
+                    address,
+                    bus.peek(address)
+                )
+            }
+            MemoryAdressingMode::IndirectY => {
+                let base = peek_word_zero_page(self.byte_operand());
+                let address = base.wrapping_add(cpu.y as u16);
+                format!(
+                    " = {:04X} @ {:04X} = {:02X}",
+                    base,
+                    address,
+                    bus.peek(address)
+                )
+            }
+            MemoryAdressingMode::ZeroPageIndirect => {
+                let address = peek_word_zero_page(self.byte_operand());
+                format!(" = {:04X} = {:02X}", address, bus.peek(address))
+            }
+            MemoryAdressingMode::Indirect => {
+                let pointer = self.word_operand();
+                let hi_pointer = if cpu.variant.has_jmp_indirect_bug() {
+                    (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF)
+                } else {
+                    pointer.wrapping_add(1)
+                };
+                let target = (bus.peek(hi_pointer) as u16) << 8 | bus.peek(pointer) as u16;
+                format!(" = {:04X}", target)
+            }
+            MemoryAdressingMode::AbsoluteIndirectX => {
+                let pointer = self.word_operand().wrapping_add(cpu.x as u16);
+                let target =
+                    (bus.peek(pointer.wrapping_add(1)) as u16) << 8 | bus.peek(pointer) as u16;
+                format!(" @ {:04X} = {:04X}", pointer, target)
+            }
+            MemoryAdressingMode::Implied
+            | MemoryAdressingMode::Accumulator
+            | MemoryAdressingMode::Immediate
+            | MemoryAdressingMode::Relative => String::new(),
+        };
+
+        let operand = self.operand();
+        if operand.is_empty() {
+            self.mnemonic().to_owned()
+        } else {
+            format!("{} {}{}", self.mnemonic(), operand, annotation)
+        }
+    }
+}
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operand = self.operand();
+        if operand.is_empty() {
+            write!(f, "{}", self.mnemonic())
+        } else {
+            write!(f, "{} {}", self.mnemonic(), operand)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::processor_status::ProcessorStatus;
+    use super::*;
+    use crate::bus::flat_ram::FlatRam;
+
+    #[test]
+    fn test_operand_syntax() {
+        // LDA ($44),Y; JMP ($FFFC); LSR A; STA $0300,X; BNE -4; CLC
+        let code = [
+            0xb1, 0x44, 0x6c, 0xfc, 0xff, 0x4a, 0x9d, 0x00, 0x03, 0xd0, 0xfc, 0x18,
+        ];
+        let lines: Vec<String> = disassemble_range(&code, 0xc000, CpuVariant::Ricoh2A03)
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "LDA ($44),Y",
+                "JMP ($FFFC)",
+                "LSR A",
+                "STA $0300,X",
+                "BNE $C007",
+                "CLC"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_drops_cut_off_instruction() {
+        let lines = disassemble_range(&[0xea, 0xad, 0x00], 0x8000, CpuVariant::Ricoh2A03);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].address, 0x8000);
+    }
+
+    #[test]
+    fn test_annotated() {
+        let mut bus = FlatRam::new();
+        // LDA ($89),Y; STY $33,X; JMP ($02FF)
+        bus.load(0x8000, &[0xb1, 0x89, 0x94, 0x33, 0x6c, 0xff, 0x02]);
+        bus.load(0x0089, &[0x00, 0x03]);
+        bus.load(0x0300, &[0x89]);
+        bus.load(0x02ff, &[0x7e]);
+        bus.load(0x0200, &[0xdb]);
+        let mut cpu =
+            CPU::new_with_state(bus, 0x8000, 0xfd, 0, 0, 0, ProcessorStatus::default(), 0);
+        cpu.x = 0x01;
+
+        let line = disassemble(&cpu.bus, 0x8000, cpu.variant);
+        assert_eq!(line.bytes, [0xb1, 0x89]);
+        assert_eq!(line.annotated(&cpu), "LDA ($89),Y = 0300 @ 0300 = 89");
+        let line = disassemble(&cpu.bus, 0x8002, cpu.variant);
+        assert_eq!(line.annotated(&cpu), "STY $33,X @ 34 = 00");
+        let line = disassemble(&cpu.bus, 0x8004, cpu.variant);
+        assert_eq!(line.annotated(&cpu), "JMP ($02FF) = DB7E");
+    }
+}
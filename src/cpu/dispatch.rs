@@ -0,0 +1,194 @@
+/*
+The opcode table the interpreter runs from: every opcode of a variant is looked up
+once, when the table is built, instead of matching on the instruction type each step.
+*/
+use super::{
+    instructions::{Instruction, InstructionType, MemoryAdressingMode},
+    variant::CpuVariant,
+    CPU,
+};
+use crate::bus::Bus;
+
+pub(crate) type Handler<B> = fn(&mut CPU<B>, &'static Instruction);
+
+pub(crate) struct Entry<B> {
+    pub instruction: &'static Instruction,
+    pub handler: Handler<B>,
+    // Single byte instructions still read the byte after the opcode and throw it away,
+    // except for the one cycle NOPs of the 65C02
+    pub dummy_read: bool,
+}
+
+pub(crate) struct DispatchTable<B> {
+    pub variant: CpuVariant,
+    pub entries: Box<[Entry<B>; 256]>,
+}
+
+impl<B> Clone for Entry<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for Entry<B> {}
+
+impl<B> Clone for DispatchTable<B> {
+    fn clone(&self) -> Self {
+        Self {
+            variant: self.variant,
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<B: Bus> DispatchTable<B> {
+    pub fn new(variant: CpuVariant) -> Self {
+        let instructions = variant.instruction_set();
+        let entries = Box::new(std::array::from_fn(|opcode| {
+            let instruction = &instructions[opcode];
+            Entry {
+                instruction,
+                handler: variant_handler(variant, instruction),
+                dummy_read: matches!(
+                    instruction.memory_addressing_mode,
+                    MemoryAdressingMode::Implied | MemoryAdressingMode::Accumulator
+                ) && instruction.cycle > 1,
+            }
+        }));
+        Self { variant, entries }
+    }
+}
+
+// Opcodes that behave differently from the rest of their instruction type
+fn variant_handler<B: Bus>(variant: CpuVariant, instruction: &Instruction) -> Handler<B> {
+    match (variant, instruction.op_code) {
+        (CpuVariant::Cmos65C02, 0x5c) => |cpu, instruction| cpu.nop_5c(instruction),
+        _ => handler(instruction.instruction_type),
+    }
+}
+
+fn handler<B: Bus>(instruction_type: InstructionType) -> Handler<B> {
+    match instruction_type {
+        InstructionType::AHX => |cpu, instruction| cpu.ahx(instruction),
+        InstructionType::ALR => |cpu, instruction| cpu.alr(instruction),
+        InstructionType::ANC => |cpu, instruction| cpu.anc(instruction),
+        InstructionType::AND => |cpu, instruction| cpu.and(instruction),
+        InstructionType::ADC => |cpu, instruction| cpu.adc(instruction),
+        InstructionType::ARR => |cpu, instruction| cpu.arr(instruction),
+        InstructionType::ASL => |cpu, instruction| cpu.asl(instruction),
+        InstructionType::AXS => |cpu, instruction| cpu.axs(instruction),
+        InstructionType::BCC => |cpu, _| cpu.bcc(),
+        InstructionType::BCS => |cpu, _| cpu.bcs(),
+        InstructionType::BEQ => |cpu, _| cpu.beq(),
+        InstructionType::BIT => |cpu, instruction| cpu.bit(instruction),
+        InstructionType::BMI => |cpu, _| cpu.bmi(),
+        InstructionType::BNE => |cpu, _| cpu.bne(),
+        InstructionType::BPL => |cpu, _| cpu.bpl(),
+        InstructionType::BRA => |cpu, _| cpu.bra(),
+        InstructionType::BRK => |cpu, _| cpu.brk(),
+        InstructionType::BVC => |cpu, _| cpu.bvc(),
+        InstructionType::BVS => |cpu, _| cpu.bvs(),
+        InstructionType::CLC => |cpu, _| cpu.clc(),
+        InstructionType::CLD => |cpu, _| cpu.cld(),
+        InstructionType::CLI => |cpu, _| cpu.cli(),
+        InstructionType::CLV => |cpu, _| cpu.clv(),
+        InstructionType::CMP => |cpu, instruction| cpu.cmp(instruction),
+        InstructionType::CPX => |cpu, instruction| cpu.cpx(instruction),
+        InstructionType::CPY => |cpu, instruction| cpu.cpy(instruction),
+        InstructionType::DCP => |cpu, instruction| cpu.dcp(instruction),
+        InstructionType::DEC => |cpu, instruction| cpu.dec(instruction),
+        InstructionType::DEX => |cpu, _| cpu.dex(),
+        InstructionType::DEY => |cpu, _| cpu.dey(),
+        InstructionType::EOR => |cpu, instruction| cpu.eor(instruction),
+        InstructionType::ISB => |cpu, instruction| cpu.isb(instruction),
+        InstructionType::INC => |cpu, instruction| cpu.inc(instruction),
+        InstructionType::INX => |cpu, _| cpu.inx(),
+        InstructionType::INY => |cpu, _| cpu.iny(),
+        InstructionType::JMP => |cpu, instruction| cpu.jmp(instruction),
+        InstructionType::JSR => |cpu, _| cpu.jsr(),
+        InstructionType::KIL => |cpu, _| cpu.kil(),
+        InstructionType::LAS => |cpu, instruction| cpu.las(instruction),
+        InstructionType::LAX => |cpu, instruction| cpu.lax(instruction),
+        InstructionType::LDA => |cpu, instruction| cpu.lda(instruction),
+        InstructionType::LDX => |cpu, instruction| cpu.ldx(instruction),
+        InstructionType::LDY => |cpu, instruction| cpu.ldy(instruction),
+        InstructionType::LSR => |cpu, instruction| cpu.lsr(instruction),
+        InstructionType::NOP => |cpu, instruction| cpu.nop(instruction),
+        InstructionType::ORA => |cpu, instruction| cpu.ora(instruction),
+        InstructionType::PHA => |cpu, _| cpu.pha(),
+        InstructionType::PHP => |cpu, _| cpu.php(),
+        InstructionType::PHX => |cpu, _| cpu.phx(),
+        InstructionType::PHY => |cpu, _| cpu.phy(),
+        InstructionType::PLA => |cpu, _| cpu.pla(),
+        InstructionType::PLP => |cpu, _| cpu.plp(),
+        InstructionType::PLX => |cpu, _| cpu.plx(),
+        InstructionType::PLY => |cpu, _| cpu.ply(),
+        InstructionType::RLA => |cpu, instruction| cpu.rla(instruction),
+        InstructionType::ROL => |cpu, instruction| cpu.rol(instruction),
+        InstructionType::ROR => |cpu, instruction| cpu.ror(instruction),
+        InstructionType::RRA => |cpu, instruction| cpu.rra(instruction),
+        InstructionType::RTI => |cpu, _| cpu.rti(),
+        InstructionType::RTS => |cpu, _| cpu.rts(),
+        InstructionType::SAX => |cpu, instruction| cpu.sax(instruction),
+        InstructionType::SHX => |cpu, instruction| cpu.shx(instruction),
+        InstructionType::SHY => |cpu, instruction| cpu.shy(instruction),
+        InstructionType::SLO => |cpu, instruction| cpu.slo(instruction),
+        InstructionType::SRE => |cpu, instruction| cpu.sre(instruction),
+        InstructionType::STA => |cpu, instruction| cpu.sta(instruction),
+        InstructionType::SBC => |cpu, instruction| cpu.sbc(instruction),
+        InstructionType::SEC => |cpu, _| cpu.sec(),
+        InstructionType::SED => |cpu, _| cpu.sed(),
+        InstructionType::SEI => |cpu, _| cpu.sei(),
+        InstructionType::STX => |cpu, instruction| cpu.stx(instruction),
+        InstructionType::STY => |cpu, instruction| cpu.sty(instruction),
+        InstructionType::STZ => |cpu, instruction| cpu.stz(instruction),
+        InstructionType::TAS => |cpu, instruction| cpu.tas(instruction),
+        InstructionType::TAX => |cpu, _| cpu.tax(),
+        InstructionType::TSX => |cpu, _| cpu.tsx(),
+        InstructionType::TXS => |cpu, _| cpu.txs(),
+        InstructionType::TXA => |cpu, _| cpu.txa(),
+        InstructionType::TAY => |cpu, _| cpu.tay(),
+        InstructionType::TRB => |cpu, instruction| cpu.trb(instruction),
+        InstructionType::TSB => |cpu, instruction| cpu.tsb(instruction),
+        InstructionType::TYA => |cpu, _| cpu.tya(),
+        InstructionType::XAA => |cpu, instruction| cpu.xaa(instruction),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::flat_ram::FlatRam;
+
+    #[test]
+    fn test_table_follows_variant() {
+        let table = DispatchTable::<FlatRam>::new(CpuVariant::Ricoh2A03);
+        for (opcode, entry) in table.entries.iter().enumerate() {
+            assert_eq!(entry.instruction.op_code as usize, opcode);
+        }
+
+        // $80 is a two byte NOP on the NMOS chips and BRA on the 65C02
+        let mut bus = FlatRam::new();
+        bus.load(0x8000, &[0x80, 0x02, 0x80, 0x02]);
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x8000;
+        assert_eq!(
+            cpu.step().instruction.instruction_type,
+            InstructionType::NOP
+        );
+        assert_eq!(cpu.program_counter, 0x8002);
+
+        cpu.variant = CpuVariant::Cmos65C02;
+        assert_eq!(
+            cpu.step().instruction.instruction_type,
+            InstructionType::BRA
+        );
+        assert_eq!(cpu.program_counter, 0x8006);
+
+        // $5C gets its own handler for the four extra bus cycles
+        cpu.bus.load(0x8006, &[0x5c, 0x34, 0x12]);
+        let step = cpu.step();
+        assert_eq!(step.instruction.instruction_type, InstructionType::NOP);
+        assert_eq!((step.cycles, cpu.program_counter), (8, 0x8009));
+    }
+}
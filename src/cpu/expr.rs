@@ -0,0 +1,274 @@
+/*
+The expression tokenizer and parser shared by the assembler and breakpoint
+conditions. Each side describes its operators and the values it knows about
+(symbols, registers, memory reads) through a Syntax and evaluates the tree
+itself, since they disagree on things like division by zero.
+
+Numbers are $hex, %binary, decimal or 'c'. % is only an operator when the syntax
+has it and it follows a value. Everything after a ; is a comment.
+*/
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Token {
+    Number(i64),
+    Name(String),
+    Text(String),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expr<T> {
+    Number(i64),
+    // Whatever the syntax adds on top of numbers
+    Term(T),
+    Unary(&'static str, Box<Expr<T>>),
+    Binary(&'static str, Box<Expr<T>>, Box<Expr<T>>),
+}
+
+pub(crate) trait Syntax: Sized {
+    type Term;
+    // Longest first so << is not read as <
+    const PUNCTUATION: &'static [&'static str];
+    // Loosest first
+    const BINARY_OPERATORS: &'static [&'static [&'static str]];
+    const UNARY_OPERATORS: &'static [&'static str];
+    // How errors call a missing operand
+    const VALUE: &'static str;
+
+    // A value that isn't a number or in parentheses, token has already been taken
+    fn term(parser: &mut Parser<Self>, token: Token) -> Result<Expr<Self::Term>, String>;
+}
+
+pub(crate) fn tokenize<S: Syntax>(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let modulo = S::PUNCTUATION.contains(&"%")
+            && matches!(
+                tokens.last(),
+                Some(Token::Number(_) | Token::Name(_) | Token::Punct(")" | "]" | "}"))
+            );
+
+        let length = match c {
+            '$' | '0'..='9' => {
+                let (radix, prefix) = if c == '$' { (16, 1) } else { (10, 0) };
+                number(rest, radix, prefix, &mut tokens)?
+            }
+            '%' if !modulo => number(rest, 2, 1, &mut tokens)?,
+            '\'' => {
+                let mut chars = rest[1..].chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), Some('\'')) if c.is_ascii() => {
+                        tokens.push(Token::Number(c as i64));
+                        3
+                    }
+                    _ => return Err("invalid character literal".to_owned()),
+                }
+            }
+            '"' => {
+                let end = rest[1..].find('"').ok_or("unterminated string")?;
+                tokens.push(Token::Text(rest[1..end + 1].to_owned()));
+                end + 2
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+                let length = rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(rest.len(), |length| length + 1);
+                tokens.push(Token::Name(rest[..length].to_owned()));
+                length
+            }
+            _ => {
+                let punct = S::PUNCTUATION
+                    .iter()
+                    .find(|punct| rest.starts_with(*punct))
+                    .ok_or_else(|| format!("unexpected character {:?}", c))?;
+                tokens.push(Token::Punct(punct));
+                punct.len()
+            }
+        };
+        rest = &rest[length..];
+    }
+    Ok(tokens)
+}
+
+fn number(rest: &str, radix: u32, prefix: usize, tokens: &mut Vec<Token>) -> Result<usize, String> {
+    let digits = rest[prefix..]
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(rest.len() - prefix);
+    let text = &rest[..prefix + digits];
+    let value = i64::from_str_radix(&text[prefix..], radix)
+        .map_err(|_| format!("invalid number {}", text))?;
+    tokens.push(Token::Number(value));
+    Ok(prefix + digits)
+}
+
+pub(crate) fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(value) => value.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Text(text) => format!("\"{}\"", text),
+        Token::Punct(punct) => punct.to_string(),
+    }
+}
+
+// The error for a token where a value should have been
+pub(crate) fn expected_value<S: Syntax>(token: &Token) -> String {
+    format!("expected {}, found {}", S::VALUE, describe(token))
+}
+
+pub(crate) struct Parser<S> {
+    pub tokens: Vec<Token>,
+    pub position: usize,
+    syntax: PhantomData<S>,
+}
+
+impl<S: Syntax> Parser<S> {
+    pub fn new(source: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize::<S>(source)?,
+            position: 0,
+            syntax: PhantomData,
+        })
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    pub fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    pub fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", punct))
+        }
+    }
+
+    pub fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {}", describe(token))),
+        }
+    }
+
+    pub fn expression(&mut self) -> Result<Expr<S::Term>, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr<S::Term>, String> {
+        if level == S::BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Punct(punct)) if S::BINARY_OPERATORS[level].contains(punct) => *punct,
+                _ => return Ok(left),
+            };
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr<S::Term>, String> {
+        match self.peek() {
+            Some(Token::Punct(operator)) if S::UNARY_OPERATORS.contains(operator) => {
+                let operator = *operator;
+                self.position += 1;
+                Ok(Expr::Unary(operator, Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr<S::Term>, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Punct("(")) => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => S::term(self, token),
+            None => Err(format!("expected {}", S::VALUE)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Just numbers and a % operator, to see the shared parts on their own
+    struct Numbers;
+
+    impl Syntax for Numbers {
+        type Term = ();
+        const PUNCTUATION: &'static [&'static str] = &["<<", "+", "*", "%", "-", "(", ")"];
+        const BINARY_OPERATORS: &'static [&'static [&'static str]] =
+            &[&["<<"], &["+"], &["*", "%"]];
+        const UNARY_OPERATORS: &'static [&'static str] = &["-"];
+        const VALUE: &'static str = "a number";
+
+        fn term(_: &mut Parser<Self>, token: Token) -> Result<Expr<()>, String> {
+            Err(expected_value::<Self>(&token))
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let number = |value| Box::new(Expr::Number(value));
+        let mut parser = Parser::<Numbers>::new("1 << -2 + $10 * (%11 % 'a') ; comment").unwrap();
+        assert_eq!(
+            parser.expression(),
+            Ok(Expr::Binary(
+                "<<",
+                number(1),
+                Box::new(Expr::Binary(
+                    "+",
+                    Box::new(Expr::Unary("-", number(2))),
+                    Box::new(Expr::Binary(
+                        "*",
+                        number(0x10),
+                        Box::new(Expr::Binary("%", number(3), number(97)))
+                    ))
+                ))
+            ))
+        );
+        assert!(parser.at_end());
+
+        let error = |source| Parser::<Numbers>::new(source).and_then(|mut p| p.expression());
+        assert_eq!(error("1 +"), Err("expected a number".to_owned()));
+        assert_eq!(error("(1"), Err("expected )".to_owned()));
+        assert_eq!(error("x"), Err("expected a number, found x".to_owned()));
+        assert_eq!(error("1 & 2"), Err("unexpected character '&'".to_owned()));
+        assert_eq!(error("$1g"), Err("invalid number $1g".to_owned()));
+    }
+}
@@ -1,30 +1,37 @@
 #[macro_export]
 macro_rules! instruction {
-    ($mnemonic:expr,$op_code:expr,$bytes:expr,$cycle:expr,$instruction_type:expr,$memory_addressing:expr,$plus_cycle:expr) => {
+    ($mnemonic:expr,$op_code:expr,$bytes:expr,$cycle:expr,$instruction_type:expr,$memory_addressing:expr,$access:expr,$stability:expr,$penalty:expr) => {
         Instruction {
-            #[cfg(debug_assertions)]
             mnemonic: $mnemonic,
-
             op_code: $op_code,
             bytes: $bytes,
             cycle: $cycle,
             instruction_type: $instruction_type,
             memory_addressing_mode: $memory_addressing,
-            plus_cycle: $plus_cycle,
+            access: $access,
+            stability: $stability,
+            penalty: $penalty,
         }
     };
 }
 
-pub mod instruction_set;
+// Generated by build.rs from ops_codes.json and ops_codes_65c02.json
+pub mod instruction_set {
+    include!(concat!(env!("OUT_DIR"), "/instruction_set.rs"));
+}
 
 use instruction_set::INSTRUCTION_SET;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum InstructionType {
-    NotImplemented,
     ADC,
+    AHX,
+    ALR,
+    ANC,
     AND,
+    ARR,
     ASL,
+    AXS,
     BCC,
     BCS,
     BEQ,
@@ -32,6 +39,7 @@ pub enum InstructionType {
     BMI,
     BNE,
     BPL,
+    BRA,
     BRK,
     BVC,
     BVS,
@@ -42,6 +50,7 @@ pub enum InstructionType {
     CMP,
     CPX,
     CPY,
+    DCP,
     DEC,
     DEX,
     DEY,
@@ -52,6 +61,9 @@ pub enum InstructionType {
     ISB,
     JMP,
     JSR,
+    KIL,
+    LAS,
+    LAX,
     LDA,
     LDX,
     LDY,
@@ -60,25 +72,41 @@ pub enum InstructionType {
     ORA,
     PHA,
     PHP,
+    PHX,
+    PHY,
     PLA,
     PLP,
+    PLX,
+    PLY,
+    RLA,
     ROL,
     ROR,
+    RRA,
     RTI,
     RTS,
+    SAX,
     SBC,
     SEC,
     SED,
     SEI,
+    SHX,
+    SHY,
+    SLO,
+    SRE,
     STA,
     STX,
     STY,
+    STZ,
+    TAS,
     TAX,
     TAY,
+    TRB,
+    TSB,
     TSX,
     TXA,
     TXS,
     TYA,
+    XAA,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -96,23 +124,66 @@ pub enum MemoryAdressingMode {
     IndirectY,
     Relative,
     Accumulator,
+    // 65C02 only: (zp) and JMP (abs,X)
+    ZeroPageIndirect,
+    AbsoluteIndirectX,
+}
+
+// What the instruction does with the bus besides fetching itself
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Access {
+    // Registers only (TAX, CLC, ASL A, KIL, ...)
+    Internal,
+    Read,
+    Write,
+    ReadModifyWrite,
+    Branch,
+    // Pushes and pulls
+    Stack,
+    // JMP, JSR, RTS, RTI and BRK
+    Jump,
+}
+
+// Ordered from the documented opcodes to the ones nobody should rely on
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Stability {
+    Official,
+    // Unofficial but behaves the same on every chip
+    Stable,
+    // Depends on the chip, its temperature or what else is on the bus (XAA, LAX #imm, SHX, ...)
+    Unstable,
+}
+
+// Extra cycles on top of Instruction::cycle
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Penalty {
+    None,
+    // +1 when the indexed address crosses a page
+    PageCross,
+    // +1 when taken, +1 more when the target is on another page
+    Branch,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Instruction {
-    #[cfg(debug_assertions)]
     pub mnemonic: &'static str,
-
     pub op_code: u8,
     pub bytes: u8,
     pub cycle: u8,
     pub instruction_type: InstructionType,
     pub memory_addressing_mode: MemoryAdressingMode,
-    pub plus_cycle: bool,
+    pub access: Access,
+    pub stability: Stability,
+    pub penalty: Penalty,
+}
+
+impl Instruction {
+    pub fn is_official(&self) -> bool {
+        self.stability == Stability::Official
+    }
 }
 
 impl std::fmt::Display for Instruction {
-    #[cfg(debug_assertions)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -120,27 +191,63 @@ impl std::fmt::Display for Instruction {
             self.op_code, self.mnemonic, self.memory_addressing_mode
         )
     }
+}
 
-    #[cfg(not(debug_assertions))]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Instruction {:#04X?}", self.op_code)
-    }
+pub fn get_instruction_from_opcode(op_code: usize) -> &'static Instruction {
+    &INSTRUCTION_SET[op_code]
 }
 
-pub const ILLEGAL_CODES: [Instruction; 1] = [instruction!(
-    "ISB",
-    0xff,
-    2,
-    7,
-    InstructionType::ISB,
-    MemoryAdressingMode::IndirectX,
-    false
-)];
+#[cfg(test)]
+mod test {
+    use super::instruction_set::INSTRUCTION_SET_65C02;
+    use super::*;
 
-pub fn get_instruction_from_opcode(op_code: usize) -> &'static Instruction {
-    if op_code >= INSTRUCTION_SET.len() {
-        &ILLEGAL_CODES[op_code as usize - INSTRUCTION_SET.len()]
-    } else {
-        &INSTRUCTION_SET[op_code as usize]
+    fn count_official(instruction_set: &[Instruction; 256]) -> usize {
+        instruction_set
+            .iter()
+            .filter(|instruction| instruction.is_official())
+            .count()
+    }
+
+    #[test]
+    fn test_official_opcodes() {
+        assert_eq!(count_official(&INSTRUCTION_SET), 151);
+        assert_eq!(count_official(&INSTRUCTION_SET_65C02), 178);
+        for (op_code, instruction) in INSTRUCTION_SET.iter().enumerate() {
+            assert_eq!(instruction.op_code as usize, op_code);
+            assert_eq!(
+                instruction.mnemonic,
+                format!("{:?}", instruction.instruction_type)
+            );
+        }
+    }
+
+    #[test]
+    fn test_access() {
+        let access = |op_code: usize| INSTRUCTION_SET[op_code].access;
+        assert_eq!(access(0xad), Access::Read); // LDA abs
+        assert_eq!(access(0x8d), Access::Write); // STA abs
+        assert_eq!(access(0xee), Access::ReadModifyWrite); // INC abs
+        assert_eq!(access(0x0a), Access::Internal); // ASL A
+        assert_eq!(access(0xd0), Access::Branch); // BNE
+        assert_eq!(access(0x48), Access::Stack); // PHA
+        assert_eq!(access(0x20), Access::Jump); // JSR
+        assert_eq!(access(0xc7), Access::ReadModifyWrite); // DCP zp
+        assert_eq!(INSTRUCTION_SET_65C02[0x64].access, Access::Write); // STZ zp
+    }
+
+    #[test]
+    fn test_stability_and_penalty() {
+        assert_eq!(INSTRUCTION_SET[0xea].stability, Stability::Official);
+        assert_eq!(INSTRUCTION_SET[0x1a].stability, Stability::Stable); // NOP
+        assert_eq!(INSTRUCTION_SET[0xeb].stability, Stability::Stable); // SBC #imm
+        assert_eq!(INSTRUCTION_SET[0xa7].stability, Stability::Stable); // LAX zp
+        assert_eq!(INSTRUCTION_SET[0xab].stability, Stability::Unstable); // LAX #imm
+        assert_eq!(INSTRUCTION_SET[0x8b].stability, Stability::Unstable); // XAA
+        assert_eq!(INSTRUCTION_SET_65C02[0x03].stability, Stability::Stable); // NOP
+
+        assert_eq!(INSTRUCTION_SET[0xbd].penalty, Penalty::PageCross); // LDA abs,X
+        assert_eq!(INSTRUCTION_SET[0x9d].penalty, Penalty::None); // STA abs,X
+        assert_eq!(INSTRUCTION_SET[0xf0].penalty, Penalty::Branch); // BEQ
     }
 }
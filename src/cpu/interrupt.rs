@@ -7,7 +7,7 @@ pub const NMI_VECTOR_END: u16 = 0xfffb;
 pub const IRQ_BRK_VECTOR: u16 = 0xfffe;
 pub const IRQ_BRK_VECTOR_END: u16 = 0xffff;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub enum InterruptType {
     NMI,
     IRQ,
@@ -26,21 +26,21 @@ pub const NMI: Interrupt = Interrupt {
     itype: InterruptType::NMI,
     vector_addr: NMI_VECTOR,
     break_flag: false,
-    cpu_cycles: 2,
+    cpu_cycles: 7,
 };
 
 pub const IRQ: Interrupt = Interrupt {
     itype: InterruptType::IRQ,
     vector_addr: IRQ_BRK_VECTOR,
     break_flag: false,
-    cpu_cycles: 2,
+    cpu_cycles: 7,
 };
 
 pub const BRK: Interrupt = Interrupt {
     itype: InterruptType::BRK,
     vector_addr: IRQ_BRK_VECTOR,
     break_flag: true,
-    cpu_cycles: 1,
+    cpu_cycles: 7,
 };
 
 impl std::fmt::Display for Interrupt {
@@ -1,66 +1,113 @@
+pub mod asm;
+pub mod breakpoint;
+pub mod call_stack;
+pub mod condition;
+pub mod disasm;
+mod dispatch;
+mod expr;
 pub mod instructions;
 pub mod interrupt;
 pub mod processor_status;
+pub mod profiler;
+pub mod trace;
+pub mod variant;
 
 use core::panic;
 use std::{fmt::Debug, ops::BitOr};
 
-use self::interrupt::{Interrupt, InterruptType, BRK, NMI};
+use self::interrupt::{Interrupt, InterruptType, BRK, IRQ, NMI, RESET_VECTOR};
 
-use super::bus::MemoryBus;
-use instructions::{
-    get_instruction_from_opcode, Instruction, InstructionType, MemoryAdressingMode,
-};
+use super::bus::{cdl::PrgUsage, AddressSpace, Bus, MemoryBus, MemoryOperation};
+use breakpoint::{Break, BreakReason, BreakpointId, Breakpoints};
+use call_stack::{CallFrame, CallStack, FrameKind};
+use dispatch::DispatchTable;
+use instructions::{Access, Instruction, InstructionType, MemoryAdressingMode, Penalty};
 use processor_status::ProcessorStatus;
+use variant::CpuVariant;
 
 const STACK: u16 = 0x0100;
-const OPCODE_EXIT: u8 = 0xf4;
+
+// Value ORed into A by the unstable XAA and LAX #imm opcodes. It differs between
+// chips (and even with temperature), 0xEE is what most 2A03s settle on.
+const UNSTABLE_MAGIC: u8 = 0xee;
 
 #[derive(Clone)]
-pub struct CPU {
+pub struct CPU<B = MemoryBus> {
     pub program_counter: u16,
     pub stack_pointer: u8,
     pub a: u8,
     pub x: u8,
     pub y: u8,
     pub processor_status: ProcessorStatus,
-    pub bus: MemoryBus,
+    pub bus: B,
     pub cycle: u64,
+    pub variant: CpuVariant,
+    // Set by the KIL/JAM opcodes; the run loop returns once the CPU is halted
+    pub halted: bool,
+    // Only run_until_break stops on these
+    pub breakpoints: Breakpoints,
+    // Where run_until_break last stopped on an Execute breakpoint, resuming runs that instruction
+    execute_stop: Option<u16>,
+    // Kept up to date by JSR, RTS, RTI and interrupts, for backtraces
+    pub call_stack: CallStack,
+    // What the interrupt lines (NMI, or IRQ with the I flag clear) looked like in the
+    // last cycle and the one before. The decision at the end of an instruction goes by
+    // the one before its last cycle, so an NMI raised on that cycle waits an
+    // instruction, and CLI, SEI and PLP only take effect after the next instruction.
+    interrupt_sample: Option<InterruptType>,
+    interrupt_poll: Option<InterruptType>,
+    // Rebuilt when the variant changes
+    dispatch: DispatchTable<B>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    pub instruction: &'static Instruction,
+    // Includes any OAM DMA the instruction set off
+    pub cycles: u16,
+    pub interrupt: Option<InterruptType>,
 }
 
-impl PartialEq for CPU {
+impl<B> PartialEq for CPU<B> {
     fn eq(&self, other: &Self) -> bool {
-        return self.x == other.x
-            && self.y == self.y
-            && self.a == self.a
-            && self.processor_status == other.processor_status;
+        self.x == other.x
+            && self.y == other.y
+            && self.a == other.a
+            && self.processor_status == other.processor_status
     }
 }
 
-impl Eq for CPU {}
+impl<B> Eq for CPU<B> {}
 
-impl std::fmt::Display for CPU {
+impl<B> std::fmt::Display for CPU<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Registers: a=[{:#04X?}] x=[{:#04X?}] y=[{:#04X?}] StackPointer=[{:#04X?}] ProgramCounter=[{:#04X?} ProcessorStatus=[{}] Cycle=[{}]]", self.a, self.x, self.y, self.stack_pointer, self.program_counter, self.processor_status, self.cycle)
     }
 }
 
-impl Debug for CPU {
+impl<B> Debug for CPU<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Registers: a=[{:#04X?}] x=[{:#04X?}] y=[{:#04X?}] StackPointer=[{:#04X?}] ProgramCounter=[{:#04X?} ProcessorStatus=[{}] Cycle=[{}]]", self.a, self.x, self.y, self.stack_pointer, self.program_counter, self.processor_status, self.cycle)
     }
 }
 
-impl CPU {
-    pub fn new(rom: MemoryBus) -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn new(bus: B) -> Self {
+        Self::new_with_variant(bus, CpuVariant::default())
+    }
+
+    // The bus is taken to be freshly powered on too, see power_on for a power cycle
+    pub fn new_with_variant(bus: B, variant: CpuVariant) -> Self {
         let mut cpu =
-            Self::new_with_state(rom, 0x8000, 0xFD, 0, 0, 0, ProcessorStatus::default(), 0);
-        cpu.reset_cpu();
+            Self::new_with_state(bus, 0x8000, 0xFD, 0, 0, 0, ProcessorStatus::default(), 0);
+        cpu.variant = variant;
+        cpu.power_on_cpu();
         cpu
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new_with_state(
-        rom: MemoryBus,
+        bus: B,
         program_counter: u16,
         stack_pointer: u8,
         a: u8,
@@ -69,335 +116,511 @@ impl CPU {
         processor_status: ProcessorStatus,
         cycle: u64,
     ) -> Self {
-        let cpu = Self {
+        Self {
             program_counter,
             stack_pointer,
             a,
             x,
             y,
             processor_status,
-            bus: rom,
+            bus,
             cycle,
-        };
-        cpu
+            variant: CpuVariant::default(),
+            halted: false,
+            breakpoints: Breakpoints::default(),
+            execute_stop: None,
+            call_stack: CallStack::default(),
+            interrupt_sample: None,
+            interrupt_poll: None,
+            dispatch: DispatchTable::new(CpuVariant::default()),
+        }
     }
 
     pub fn start_with_callback<F>(&mut self, mut callback: F)
     where
-        F: FnMut(&mut CPU, &Instruction),
+        F: FnMut(&mut CPU<B>, &Instruction),
     {
-        loop {
-            let program_counter_state = self.program_counter;
-            let instruction = get_instruction_from_opcode(self.read_next_byte() as usize);
-
-            if let Some(nmi) = self.bus.poll_nmi_status() {
-                match nmi {
-                    InterruptType::NMI => self.interrupt(&NMI),
-                    _ => panic!("non-nmi interrupt sent: {:?}", nmi),
-                }
+        while !self.halted {
+            let step = self.step();
+            callback(self, step.instruction);
+        }
+    }
+
+    // Runs a single instruction (servicing a pending interrupt first). The rest
+    // of the system is advanced by every bus access the CPU makes on the way.
+    pub fn step(&mut self) -> StepResult {
+        let start = self.cycle;
+        let interrupt = self.service_interrupt();
+        let instruction = self.execute_next();
+
+        StepResult {
+            instruction,
+            cycles: (self.cycle - start) as u16,
+            interrupt,
+        }
+    }
+
+    fn service_interrupt(&mut self) -> Option<InterruptType> {
+        let interrupt = self.poll_interrupts();
+        match interrupt {
+            Some(InterruptType::NMI) => {
+                self.bus.acknowledge_nmi();
+                self.interrupt(&NMI);
             }
-            if cfg!(debug_assertions) {
-                println!("INSTRUCTION: {:?}", instruction);
+            Some(InterruptType::IRQ) => self.interrupt(&IRQ),
+            _ => {}
+        }
+        interrupt
+    }
+
+    fn execute_next(&mut self) -> &'static Instruction {
+        if self.dispatch.variant != self.variant {
+            self.dispatch = DispatchTable::new(self.variant);
+        }
+        let entry = self.dispatch.entries[self.read_next_byte() as usize];
+        if entry.dummy_read {
+            self.read(self.program_counter);
+        }
+        (entry.handler)(self, entry.instruction);
+        entry.instruction
+    }
+
+    /*
+    Runs like run_until but stops when a breakpoint is hit and says which one.
+    Execute breakpoints are checked before the instruction (and before an interrupt
+    handler's first one), everything else after it. Calling this again after an
+    Execute break runs the instruction it stopped on instead of stopping again.
+    */
+    pub fn run_until_break<F>(&mut self, mut predicate: F) -> Option<Break>
+    where
+        F: FnMut(&CPU<B>) -> bool,
+    {
+        let watch_devices = self.breakpoints.watches(AddressSpace::Ppu)
+            || self.breakpoints.watches(AddressSpace::Oam);
+        self.bus.record_accesses(watch_devices);
+        self.breakpoints.arm(true);
+
+        let mut resumed_at = self.execute_stop.take();
+        let result = loop {
+            if self.halted {
+                break None;
             }
-            let cycles: u8 = match instruction.instruction_type {
-                InstructionType::AND => self.and(instruction),
-                InstructionType::ADC => self.adc(instruction),
-                InstructionType::ASL => self.asl(instruction),
-                InstructionType::BCC => self.bcc(instruction),
-                InstructionType::BCS => self.bcs(instruction),
-                InstructionType::BEQ => self.beq(instruction),
-                InstructionType::BIT => self.bit(instruction),
-                InstructionType::BMI => self.bmi(instruction),
-                InstructionType::BNE => self.bne(instruction),
-                InstructionType::BPL => self.bpl(instruction),
-                InstructionType::BRK => self.brk(instruction),
-                InstructionType::BVC => self.bvc(instruction),
-                InstructionType::BVS => self.bvs(instruction),
-                InstructionType::CLC => self.clc(instruction),
-                InstructionType::CLD => self.cld(instruction),
-                InstructionType::CLI => self.cli(instruction),
-                InstructionType::CLV => self.clv(instruction),
-                InstructionType::CMP => self.cmp(instruction),
-                InstructionType::CPX => self.cpx(instruction),
-                InstructionType::CPY => self.cpy(instruction),
-                InstructionType::DEC => self.dec(instruction),
-                InstructionType::DEX => self.dex(instruction),
-                InstructionType::DEY => self.dey(instruction),
-                InstructionType::EOR => self.eor(instruction),
-                InstructionType::ISB => self.isb(instruction),
-                InstructionType::INC => self.inc(instruction),
-                InstructionType::INX => self.inx(instruction),
-                InstructionType::INY => self.iny(instruction),
-                InstructionType::JMP => self.jmp(instruction),
-                InstructionType::JSR => self.jsr(instruction),
-                InstructionType::LDA => self.lda(instruction),
-                InstructionType::LDX => self.ldx(instruction),
-                InstructionType::LDY => self.ldy(instruction),
-                InstructionType::LSR => self.lsr(instruction),
-                InstructionType::NOP => self.nop(instruction),
-                InstructionType::ORA => self.ora(instruction),
-                InstructionType::PHA => self.pha(instruction),
-                InstructionType::PHP => self.php(instruction),
-                InstructionType::PLA => self.pla(instruction),
-                InstructionType::PLP => self.plp(instruction),
-                InstructionType::ROL => self.rol(instruction),
-                InstructionType::ROR => self.ror(instruction),
-                InstructionType::RTI => self.rti(instruction),
-                InstructionType::RTS => self.rts(instruction),
-                InstructionType::STA => self.sta(instruction),
-                InstructionType::SBC => self.sbc(instruction),
-                InstructionType::SEC => self.sec(instruction),
-                InstructionType::SED => self.sed(instruction),
-                InstructionType::SEI => self.sei(instruction),
-                InstructionType::STX => self.stx(instruction),
-                InstructionType::STY => self.sty(instruction),
-                InstructionType::TAX => self.tax(instruction),
-                InstructionType::TSX => self.tsx(instruction),
-                InstructionType::TXS => self.txs(instruction),
-                InstructionType::TXA => self.txa(instruction),
-                InstructionType::TAY => self.tay(instruction),
-                InstructionType::TYA => self.tya(instruction),
-                InstructionType::NotImplemented => {
-                    if instruction.op_code == OPCODE_EXIT {
-                        return;
-                    }
-                    println!("Not implemented! {}", instruction);
-                    self.nop(instruction)
+            if resumed_at.take() != Some(self.program_counter) {
+                if let Some(hit) = self.execute_break() {
+                    break Some(hit);
                 }
-            };
+            }
 
-            self.cycle += cycles as u64;
-            callback(self, instruction);
-            if self.processor_status.contains(ProcessorStatus::BREAK) {
-                self.interrupt(&BRK);
+            let position = self.bus.ppu_position();
+            if let Some(interrupt) = self.service_interrupt() {
+                let hits = self.breakpoints.interrupt_hits(interrupt);
+                if let Some(hit) = self.first_met(hits, self.program_counter) {
+                    break Some(hit);
+                }
+                if let Some(hit) = self.execute_break() {
+                    break Some(hit);
+                }
             }
 
-            self.bus.tick(cycles);
-            if cfg!(debug_assertions) {
-                println!("CPU: {}", self);
+            // Accesses made by an interrupt sequence go down to the handler's first instruction
+            let program_counter = self.program_counter;
+            let instruction = self.execute_next();
+            if instruction.instruction_type == InstructionType::BRK {
+                let hits = self.breakpoints.interrupt_hits(InterruptType::BRK);
+                if let Some(hit) = self.first_met(hits, self.program_counter) {
+                    break Some(hit);
+                }
+            }
+            if watch_devices {
+                for access in self.bus.take_accesses() {
+                    self.breakpoints.watch(access);
+                }
+            }
+            let mut hits = self.breakpoints.take_hits();
+            if let Some((from, to)) = position.zip(self.bus.ppu_position()) {
+                hits.extend(self.breakpoints.position_hits(from, to));
             }
-            if program_counter_state == self.program_counter {
-                self.program_counter += (cycles - 1) as u16;
+            if let Some(hit) = self.first_met(hits, program_counter) {
+                break Some(hit);
+            }
+
+            if predicate(self) {
+                break None;
+            }
+        };
+        self.bus.record_accesses(false);
+        self.breakpoints.arm(false);
+        self.execute_stop = result
+            .filter(|hit| hit.reason == BreakReason::Execute)
+            .map(|hit| hit.program_counter);
+        result
+    }
+
+    fn execute_break(&mut self) -> Option<Break> {
+        let opcode = self.bus.peek(self.program_counter);
+        let instruction = &self.variant.instruction_set()[opcode as usize];
+        let hits = self
+            .breakpoints
+            .execute_hits(self.program_counter, instruction);
+        self.first_met(hits, self.program_counter)
+    }
+
+    // Every triggered breakpoint counts a hit, the first one whose condition holds stops the CPU
+    fn first_met(
+        &mut self,
+        hits: Vec<(BreakpointId, BreakReason)>,
+        program_counter: u16,
+    ) -> Option<Break> {
+        let mut result = None;
+        for (id, reason) in hits {
+            if Breakpoints::trigger(self, id, &reason) && result.is_none() {
+                result = Some(Break {
+                    id,
+                    reason,
+                    program_counter,
+                    cycle: self.cycle,
+                });
             }
         }
+        result
     }
 
-    fn interrupt(&mut self, interrupt: &Interrupt) {
-        if self
-            .processor_status
-            .contains(ProcessorStatus::INTERRUPT_DISABLE)
-            && !matches!(interrupt.itype, InterruptType::IRQ)
+    // Each of the run_* methods returns the number of cycles it ran for and
+    // stops early if the CPU halts.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> u64 {
+        let start = self.cycle;
+        while !self.halted && self.cycle - start < cycles {
+            self.step();
+        }
+        self.cycle - start
+    }
+
+    pub fn run_until<F>(&mut self, mut predicate: F) -> u64
+    where
+        F: FnMut(&CPU<B>) -> bool,
+    {
+        let start = self.cycle;
+        while !self.halted {
+            self.step();
+            if predicate(self) {
+                break;
+            }
+        }
+        self.cycle - start
+    }
+
+    fn poll_interrupts(&mut self) -> Option<InterruptType> {
+        self.interrupt_poll
+    }
+
+    // NMI always wins, IRQ only gets through while the I flag is clear
+    // The I flag is only looked at while something holds the IRQ line, which is rare
+    fn sample_interrupts(&mut self) {
+        self.interrupt_poll = self.interrupt_sample;
+        self.interrupt_sample = if self.bus.nmi_pending() {
+            Some(InterruptType::NMI)
+        } else if self.bus.irq_line()
+            && !self
+                .processor_status
+                .contains(ProcessorStatus::INTERRUPT_DISABLE)
         {
-            return;
+            Some(InterruptType::IRQ)
+        } else {
+            None
+        };
+    }
+
+    fn interrupt(&mut self, interrupt: &Interrupt) {
+        if interrupt.itype != InterruptType::BRK {
+            // Hardware interrupts spend two cycles fetching the next opcode without running it
+            self.read(self.program_counter);
+            self.read(self.program_counter);
         }
-        // if interrupt disable flag is set and interrupt type is not IRQ
-        println!("Handling Interrupt: {}", interrupt);
+        let (return_address, stack_pointer) = (self.program_counter, self.stack_pointer);
         self.push_word(self.program_counter);
-        let mut flag = self.processor_status.clone();
-        flag.insert(ProcessorStatus::BREAK);
+        // B only exists on the stack copy, it tells the handler whether BRK got it there
+        let mut flag = self.processor_status;
+        flag.set(ProcessorStatus::BREAK, interrupt.break_flag);
         flag.insert(ProcessorStatus::BREAK2);
 
         self.push(flag.bits());
         self.processor_status
             .insert(ProcessorStatus::INTERRUPT_DISABLE);
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.processor_status.set_decimal(false);
+        }
+
+        /*
+        The vector is picked only after the pushes, so an NMI that shows up while a
+        BRK or IRQ is pushing hijacks it: the NMI handler runs (with the pushed B flag
+        left as is) and the NMI itself is not taken again afterwards.
+        */
+        let vector_addr = if interrupt.itype != InterruptType::NMI && self.bus.nmi_pending() {
+            self.bus.acknowledge_nmi();
+            NMI.vector_addr
+        } else {
+            interrupt.vector_addr
+        };
+        self.program_counter = self.read_word(vector_addr);
+
+        let itype = if vector_addr == NMI.vector_addr {
+            InterruptType::NMI
+        } else {
+            interrupt.itype
+        };
+        let caller = if interrupt.itype == InterruptType::BRK {
+            return_address.wrapping_sub(2)
+        } else {
+            return_address
+        };
+        self.push_call(
+            FrameKind::Interrupt(itype),
+            caller,
+            return_address,
+            stack_pointer,
+        );
+    }
+
+    fn push_call(&mut self, kind: FrameKind, caller: u16, return_address: u16, stack_pointer: u8) {
+        self.call_stack.push(CallFrame {
+            kind,
+            caller,
+            target: self.program_counter,
+            return_address,
+            stack_pointer,
+            frame: self.bus.ppu_frame().unwrap_or(0),
+        });
+    }
 
-        self.bus.tick(interrupt.cpu_cycles);
-        self.program_counter = self.bus.read_word(interrupt.vector_addr);
+    // The current instruction followed by the calls that led to it, innermost first
+    pub fn backtrace(&self) -> String {
+        let mut lines = vec![format!("#0 ${:04X}", self.program_counter)];
+        for (depth, frame) in self.call_stack.frames().iter().rev().enumerate() {
+            lines.push(format!("#{} {}", depth + 1, frame));
+        }
+        if let Some(imbalance) = self.call_stack.last_imbalance() {
+            lines.push(format!(
+                "stack went out of step {} time(s), last at {}",
+                self.call_stack.imbalances(),
+                imbalance
+            ));
+        }
+        lines.join("\n")
     }
 
     /*
     Instructions
     */
 
-    fn adc(&mut self, instruction: &Instruction) -> u8 {
-        let (data, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
-        self.a = self.add(self.a, data);
+    fn adc(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.add_with_carry(data);
+    }
+
+    fn ahx(&mut self, instruction: &Instruction) {
+        self.store_and_high(instruction, self.a & self.x);
+    }
+
+    fn alr(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.a = self.shift_right(self.a & data);
         self.set_negative_and_zero_process_status(self.a);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn and(&mut self, instruction: &Instruction) -> u8 {
-        let (data, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn anc(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.a &= data;
+        self.set_negative_and_zero_process_status(self.a);
+        self.processor_status
+            .set_carry(ProcessorStatus::is_negative(self.a));
+    }
+
+    fn and(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
 
         self.a &= data;
         self.set_negative_and_zero_process_status(self.a);
+    }
 
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
+    fn arr(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.a = self.rotate_right(self.a & data);
+        self.set_negative_and_zero_process_status(self.a);
+        // C and V come from bits 6 and 5 of the result rather than the rotate
+        self.processor_status.set_carry(self.a & 0b0100_0000 != 0);
+        self.processor_status
+            .set_overflow(((self.a >> 6) ^ (self.a >> 5)) & 0b1 == 1);
     }
 
-    fn asl(&mut self, instruction: &Instruction) -> u8 {
+    fn asl(&mut self, instruction: &Instruction) {
         match instruction.memory_addressing_mode {
             MemoryAdressingMode::Accumulator => {
-                self.processor_status.set_carry(self.a >> 7 == 1);
-                self.a <<= 1;
+                self.a = self.shift_left(self.a);
                 self.set_negative_and_zero_process_status(self.a);
             }
-            MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for lsr"),
+            MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for asl"),
             _ => {
-                let (address, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-                let mut data = self.bus.read_byte(address);
-                self.processor_status.set_carry(data >> 7 == 1);
-                data <<= 1;
-                self.bus.write_byte(address, data);
+                let data = self.read_modify_write(instruction, Self::shift_left);
                 self.set_negative_and_zero_process_status(data);
             }
         }
-        instruction.cycle
     }
 
-    fn bcc(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(!self.processor_status.contains(ProcessorStatus::CARRY)) + instruction.cycle
+    fn axs(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        let value = self.a & self.x;
+        self.processor_status.set_carry(value >= data);
+        self.x = value.wrapping_sub(data);
+        self.set_negative_and_zero_process_status(self.x);
+    }
+
+    fn bcc(&mut self) {
+        self.branch(!self.processor_status.contains(ProcessorStatus::CARRY))
     }
 
-    fn bcs(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(self.processor_status.contains(ProcessorStatus::CARRY)) + instruction.cycle
+    fn bcs(&mut self) {
+        self.branch(self.processor_status.contains(ProcessorStatus::CARRY))
     }
 
-    fn beq(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(self.processor_status.contains(ProcessorStatus::ZERO)) + instruction.cycle
+    fn beq(&mut self) {
+        self.branch(self.processor_status.contains(ProcessorStatus::ZERO))
     }
 
-    fn bmi(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(self.processor_status.contains(ProcessorStatus::NEGATIVE)) + instruction.cycle
+    fn bmi(&mut self) {
+        self.branch(self.processor_status.contains(ProcessorStatus::NEGATIVE))
     }
 
-    fn bne(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(!self.processor_status.contains(ProcessorStatus::ZERO)) + instruction.cycle
+    fn bne(&mut self) {
+        self.branch(!self.processor_status.contains(ProcessorStatus::ZERO))
     }
 
-    fn bpl(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(!self.processor_status.contains(ProcessorStatus::NEGATIVE)) + instruction.cycle
+    fn bpl(&mut self) {
+        self.branch(!self.processor_status.contains(ProcessorStatus::NEGATIVE))
     }
 
-    fn bvc(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(!self.processor_status.contains(ProcessorStatus::OVERFLOW)) + instruction.cycle
+    fn bvc(&mut self) {
+        self.branch(!self.processor_status.contains(ProcessorStatus::OVERFLOW))
     }
 
-    fn bvs(&mut self, instruction: &Instruction) -> u8 {
-        self.branch(self.processor_status.contains(ProcessorStatus::OVERFLOW)) + instruction.cycle
+    fn bvs(&mut self) {
+        self.branch(self.processor_status.contains(ProcessorStatus::OVERFLOW))
     }
 
-    fn bit(&mut self, instruction: &Instruction) -> u8 {
-        let (data, _page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn bit(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
         self.processor_status.set_zero(data & self.a == 0);
-        self.processor_status.set_negative(data & 0b10000000 > 0);
-        self.processor_status.set_overflow(data & 0b01000000 > 0);
-        instruction.cycle
+        // The 65C02 BIT #imm only touches Z
+        if instruction.memory_addressing_mode != MemoryAdressingMode::Immediate {
+            self.processor_status.set_negative(data & 0b10000000 > 0);
+            self.processor_status.set_overflow(data & 0b01000000 > 0);
+        }
     }
 
-    fn brk(&mut self, instruction: &Instruction) -> u8 {
+    fn bra(&mut self) {
+        self.branch(true)
+    }
+
+    fn brk(&mut self) {
+        // The byte after BRK has already been read and thrown away, it is skipped over
+        // so the return address is BRK + 2
+        self.program_counter = self.program_counter.wrapping_add(1);
         self.interrupt(&BRK);
-        instruction.cycle
     }
 
-    fn clc(&mut self, instruction: &Instruction) -> u8 {
+    fn clc(&mut self) {
         self.processor_status.set_carry(false);
-        instruction.cycle
     }
 
-    fn cld(&mut self, instruction: &Instruction) -> u8 {
+    fn cld(&mut self) {
         self.processor_status.set_decimal(false);
-        instruction.cycle
     }
 
-    fn cli(&mut self, instruction: &Instruction) -> u8 {
+    fn cli(&mut self) {
         self.processor_status.set_interrupt_disable(false);
-        instruction.cycle
     }
 
-    fn clv(&mut self, instruction: &Instruction) -> u8 {
+    fn clv(&mut self) {
         self.processor_status.set_overflow(false);
-        instruction.cycle
     }
 
-    fn cmp(&mut self, instruction: &Instruction) -> u8 {
-        let (data, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn cmp(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
         self.compare(self.a, data);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn cpx(&mut self, instruction: &Instruction) -> u8 {
-        let (data, _page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn cpx(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
         self.compare(self.x, data);
-        instruction.cycle
     }
 
-    fn cpy(&mut self, instruction: &Instruction) -> u8 {
-        let (data, _page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn cpy(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
         self.compare(self.y, data);
-        instruction.cycle
     }
 
-    fn dec(&mut self, instruction: &Instruction) -> u8 {
-        let (addr, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-        let value = self.bus.read_byte(addr);
-        let new_value = value.wrapping_sub(1);
-        self.bus.write_byte(addr, new_value);
-        self.set_negative_and_zero_process_status(new_value);
-        instruction.cycle
+    fn dcp(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, |_, data| data.wrapping_sub(1));
+        self.compare(self.a, data);
     }
 
-    fn dex(&mut self, instruction: &Instruction) -> u8 {
+    fn dec(&mut self, instruction: &Instruction) {
+        match instruction.memory_addressing_mode {
+            MemoryAdressingMode::Accumulator => {
+                self.a = self.a.wrapping_sub(1);
+                self.set_negative_and_zero_process_status(self.a);
+            }
+            _ => {
+                let data = self.read_modify_write(instruction, |_, data| data.wrapping_sub(1));
+                self.set_negative_and_zero_process_status(data);
+            }
+        }
+    }
+
+    fn dex(&mut self) {
         self.x = self.x.wrapping_sub(1);
         self.set_negative_and_zero_process_status(self.x);
-        instruction.cycle
     }
 
-    fn dey(&mut self, instruction: &Instruction) -> u8 {
+    fn dey(&mut self) {
         self.y = self.y.wrapping_sub(1);
         self.set_negative_and_zero_process_status(self.y);
-        instruction.cycle
     }
 
-    fn eor(&mut self, instruction: &Instruction) -> u8 {
-        let (a, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn eor(&mut self, instruction: &Instruction) {
+        let a = self.read_byte(instruction);
         self.a ^= a;
         self.set_negative_and_zero_process_status(self.a);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn inc(&mut self, instruction: &Instruction) -> u8 {
-        let (addr, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-        let value = self.bus.read_byte(addr);
-        let new_value = value.wrapping_add(1);
-        self.bus.write_byte(addr, new_value);
-        self.set_negative_and_zero_process_status(new_value);
-        instruction.cycle
+    fn inc(&mut self, instruction: &Instruction) {
+        match instruction.memory_addressing_mode {
+            MemoryAdressingMode::Accumulator => {
+                self.a = self.a.wrapping_add(1);
+                self.set_negative_and_zero_process_status(self.a);
+            }
+            _ => {
+                let data = self.read_modify_write(instruction, |_, data| data.wrapping_add(1));
+                self.set_negative_and_zero_process_status(data);
+            }
+        }
     }
 
-    fn inx(&mut self, instruction: &Instruction) -> u8 {
+    fn inx(&mut self) {
         self.x = self.x.wrapping_add(1);
         self.set_negative_and_zero_process_status(self.x);
-        instruction.cycle
     }
 
-    fn iny(&mut self, instruction: &Instruction) -> u8 {
+    fn iny(&mut self) {
         self.y = self.y.wrapping_add(1);
         self.set_negative_and_zero_process_status(self.y);
-        instruction.cycle
     }
 
-    fn isb(&mut self, instruction: &Instruction) -> u8 {
-        todo!("{:?}", instruction)
+    fn isb(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, |_, data| data.wrapping_add(1));
+        self.subtract_with_borrow(data);
     }
 
-    fn jmp(&mut self, instruction: &Instruction) -> u8 {
+    fn jmp(&mut self, instruction: &Instruction) {
         let addr = match instruction.memory_addressing_mode {
             MemoryAdressingMode::Indirect => {
                 /*
@@ -409,325 +632,400 @@ impl CPU {
                 ensure the indirect vector is not at the end of the page.
                 */
                 let addr = self.read_next_word();
-                if addr & 0x00ff == 0x00ff {
-                    let lo = self.bus.read_byte(addr);
-                    let hi = self.bus.read_byte(addr & 0xFF00);
+                if self.variant.has_jmp_indirect_bug() {
+                    let lo = self.read_data(addr);
+                    let hi = self.read_data((addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF));
                     (hi as u16) << 8 | (lo as u16)
                 } else {
-                    self.bus.read_word(addr)
+                    // The fix costs the 65C02 an extra cycle
+                    self.read(self.program_counter.wrapping_sub(1));
+                    self.read_word(addr)
                 }
             }
+            MemoryAdressingMode::AbsoluteIndirectX => {
+                let addr = self.read_next_word();
+                self.read(self.program_counter.wrapping_sub(1));
+                self.read_word(addr.wrapping_add(self.x as u16))
+            }
             MemoryAdressingMode::Absolute => self.read_next_word(),
             _ => panic!("Not support for jmp"),
         };
+        if instruction.memory_addressing_mode != MemoryAdressingMode::Absolute {
+            self.bus.log_code_data(addr, PrgUsage::INDIRECT_CODE);
+        }
         self.program_counter = addr;
-        instruction.cycle
     }
 
-    fn jsr(&mut self, instruction: &Instruction) -> u8 {
-        // so that the pc is incremented appropratiely
-        let (addr, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
+    fn jsr(&mut self) {
+        // The return point pushed is the last byte of the JSR, the high byte of
+        // the target is only fetched after the push
+        let caller = self.program_counter.wrapping_sub(1);
+        let stack_pointer = self.stack_pointer;
+        let lo = self.read_next_byte();
+        self.read(STACK + self.stack_pointer as u16);
+        self.push_word(self.program_counter);
+        self.bus.log_code_data(self.program_counter, PrgUsage::CODE);
+        let hi = self.read(self.program_counter);
+
+        self.program_counter = (hi as u16) << 8 | (lo as u16);
+        self.push_call(
+            FrameKind::Subroutine,
+            caller,
+            caller.wrapping_add(3),
+            stack_pointer,
+        );
+    }
 
-        let return_point = self.program_counter - 1;
-        self.push_word(return_point);
+    fn kil(&mut self) {
+        self.halted = true;
+    }
 
-        self.program_counter = addr;
-        instruction.cycle
+    fn las(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        let value = data & self.stack_pointer;
+        self.a = value;
+        self.x = value;
+        self.stack_pointer = value;
+        self.set_negative_and_zero_process_status(value);
+    }
+
+    fn lax(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        let value = match instruction.memory_addressing_mode {
+            MemoryAdressingMode::Immediate => (self.a | UNSTABLE_MAGIC) & data,
+            _ => data,
+        };
+        self.a = value;
+        self.x = value;
+        self.set_negative_and_zero_process_status(value);
     }
 
-    fn lda(&mut self, instruction: &Instruction) -> u8 {
-        let (a, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn lda(&mut self, instruction: &Instruction) {
+        let a = self.read_byte(instruction);
         self.a = a;
         self.set_negative_and_zero_process_status(self.a);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn ldx(&mut self, instruction: &Instruction) -> u8 {
-        let (x, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn ldx(&mut self, instruction: &Instruction) {
+        let x = self.read_byte(instruction);
         self.x = x;
         self.set_negative_and_zero_process_status(self.x);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn ldy(&mut self, instruction: &Instruction) -> u8 {
-        let (y, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn ldy(&mut self, instruction: &Instruction) {
+        let y = self.read_byte(instruction);
         self.y = y;
         self.set_negative_and_zero_process_status(self.y);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn lsr(&mut self, instruction: &Instruction) -> u8 {
+    fn lsr(&mut self, instruction: &Instruction) {
         match instruction.memory_addressing_mode {
             MemoryAdressingMode::Accumulator => {
-                self.processor_status.set_carry(self.a & 0b0000_0001 == 1);
-                self.a >>= 1;
+                self.a = self.shift_right(self.a);
                 self.set_negative_and_zero_process_status(self.a);
             }
             MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for lsr"),
             _ => {
-                let (address, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-                let mut data = self.bus.read_byte(address);
-                self.processor_status.set_carry(data & 0b0000_0001 == 1);
-                data >>= 1;
-                self.bus.write_byte(address, data);
+                let data = self.read_modify_write(instruction, Self::shift_right);
                 self.set_negative_and_zero_process_status(data);
             }
         }
+    }
 
-        instruction.cycle
+    fn nop(&mut self, instruction: &Instruction) {
+        // The unofficial NOPs with an operand read it like LDA would
+        if instruction.access == Access::Read {
+            self.read_byte(instruction);
+        }
     }
 
-    fn nop(&mut self, instruction: &Instruction) -> u8 {
-        // Account for illegal instructions
-        let (_, page_cross) = match instruction.memory_addressing_mode {
-            MemoryAdressingMode::Implied => (0, false),
-            _ => self.read_byte(&instruction.memory_addressing_mode),
-        };
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
+    // $5C on the 65C02 keeps the bus busy for another four cycles after the read
+    fn nop_5c(&mut self, instruction: &Instruction) {
+        self.nop(instruction);
+        for _ in 0..4 {
+            self.read(0xffff);
         }
     }
 
-    fn ora(&mut self, instruction: &Instruction) -> u8 {
-        let (data, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
+    fn ora(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
         self.a |= data;
         self.set_negative_and_zero_process_status(self.a);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
     }
 
-    fn pha(&mut self, instruction: &Instruction) -> u8 {
+    fn pha(&mut self) {
         self.push(self.a);
-        instruction.cycle
     }
 
-    fn php(&mut self, instruction: &Instruction) -> u8 {
+    fn php(&mut self) {
         self.push(self.processor_status.bitor(ProcessorStatus::BREAK).bits());
-        instruction.cycle
     }
 
-    fn pla(&mut self, instruction: &Instruction) -> u8 {
+    fn phx(&mut self) {
+        self.push(self.x);
+    }
+
+    fn phy(&mut self) {
+        self.push(self.y);
+    }
+
+    fn pla(&mut self) {
+        self.read(STACK + self.stack_pointer as u16);
         let data = self.pop();
         self.a = data;
         self.set_negative_and_zero_process_status(self.a);
-        instruction.cycle
     }
 
-    fn plp(&mut self, instruction: &Instruction) -> u8 {
+    fn plp(&mut self) {
+        self.read(STACK + self.stack_pointer as u16);
         self.processor_status = ProcessorStatus::from_bits_truncate(self.pop());
         self.processor_status.remove(ProcessorStatus::BREAK);
         self.processor_status.insert(ProcessorStatus::BREAK2);
-        instruction.cycle
     }
 
-    fn rol(&mut self, instruction: &Instruction) -> u8 {
+    fn plx(&mut self) {
+        self.read(STACK + self.stack_pointer as u16);
+        self.x = self.pop();
+        self.set_negative_and_zero_process_status(self.x);
+    }
+
+    fn ply(&mut self) {
+        self.read(STACK + self.stack_pointer as u16);
+        self.y = self.pop();
+        self.set_negative_and_zero_process_status(self.y);
+    }
+
+    fn rla(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, Self::rotate_left);
+        self.a &= data;
+        self.set_negative_and_zero_process_status(self.a);
+    }
+
+    fn rol(&mut self, instruction: &Instruction) {
         match instruction.memory_addressing_mode {
             MemoryAdressingMode::Accumulator => {
-                let carry = self.processor_status.contains(ProcessorStatus::CARRY);
-                self.processor_status
-                    .set_carry(self.a & 0b0100_0000 != 0b0100_0000);
-                self.a <<= 1;
-                if carry {
-                    self.a |= 0b0000_0001
-                }
+                self.a = self.rotate_left(self.a);
                 self.set_negative_and_zero_process_status(self.a);
             }
-            MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for ror"),
+            MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for rol"),
             _ => {
-                let (address, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-                let mut data = self.bus.read_byte(address);
-                let carry = self.processor_status.contains(ProcessorStatus::CARRY);
-                self.processor_status
-                    .set_carry(data & 0b0100_0000 != 0b0100_0000);
-                data <<= 1;
-                if carry {
-                    data |= 0b0000_0001
-                }
-                self.bus.write_byte(address, data);
+                let data = self.read_modify_write(instruction, Self::rotate_left);
                 self.set_negative_and_zero_process_status(data);
             }
         }
-
-        instruction.cycle
     }
 
-    fn ror(&mut self, instruction: &Instruction) -> u8 {
+    fn ror(&mut self, instruction: &Instruction) {
         match instruction.memory_addressing_mode {
             MemoryAdressingMode::Accumulator => {
-                let carry = self.processor_status.contains(ProcessorStatus::CARRY);
-                self.processor_status
-                    .set_carry(self.a & 0b0000_0001 == 0b0000_0001);
-                self.a >>= 1;
-                if carry {
-                    self.a |= 0b1000_0000
-                }
+                self.a = self.rotate_right(self.a);
                 self.set_negative_and_zero_process_status(self.a);
             }
             MemoryAdressingMode::Immediate => panic!("immediate addressing not supported for ror"),
             _ => {
-                let (address, _page_cross) = self.get_address(&instruction.memory_addressing_mode);
-                let mut data = self.bus.read_byte(address);
-                let carry = self.processor_status.contains(ProcessorStatus::CARRY);
-                self.processor_status
-                    .set_carry(data & 0b0000_0001 == 0b0000_0001);
-                data >>= 1;
-                if carry {
-                    data |= 0b1000_0000
-                }
-                self.bus.write_byte(address, data);
+                let data = self.read_modify_write(instruction, Self::rotate_right);
                 self.set_negative_and_zero_process_status(data);
             }
         }
-        instruction.cycle
     }
 
-    fn rts(&mut self, instruction: &Instruction) -> u8 {
-        self.program_counter = self.pop_word() + 1;
-        instruction.cycle
+    fn rra(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, Self::rotate_right);
+        self.add_with_carry(data);
+    }
+
+    fn rts(&mut self) {
+        let instruction_address = self.program_counter.wrapping_sub(1);
+        self.read(STACK + self.stack_pointer as u16);
+        let return_point = self.pop_word();
+        self.read(return_point);
+        self.program_counter = return_point.wrapping_add(1);
+        self.call_stack.pop(
+            instruction_address,
+            self.program_counter,
+            self.stack_pointer,
+        );
     }
 
-    fn rti(&mut self, instruction: &Instruction) -> u8 {
+    fn rti(&mut self) {
+        let instruction_address = self.program_counter.wrapping_sub(1);
+        self.read(STACK + self.stack_pointer as u16);
         self.processor_status = ProcessorStatus::from_bits_truncate(self.pop());
         self.processor_status.remove(ProcessorStatus::BREAK);
         self.processor_status.insert(ProcessorStatus::BREAK2);
         self.program_counter = self.pop_word();
-        instruction.cycle
+        self.call_stack.pop(
+            instruction_address,
+            self.program_counter,
+            self.stack_pointer,
+        );
     }
 
-    fn sbc(&mut self, instruction: &Instruction) -> u8 {
-        let (data, page_cross) = self.read_byte(&instruction.memory_addressing_mode);
-        self.a = self.add(self.a, (data as i8).wrapping_neg().wrapping_sub(1) as u8);
-        self.set_negative_and_zero_process_status(self.a);
-        if page_cross {
-            instruction.cycle + 1
-        } else {
-            instruction.cycle
-        }
+    fn sax(&mut self, instruction: &Instruction) {
+        self.write_byte(instruction, self.a & self.x);
+    }
+
+    fn sbc(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.subtract_with_borrow(data);
     }
 
-    fn sec(&mut self, instruction: &Instruction) -> u8 {
+    fn sec(&mut self) {
         self.processor_status.set_carry(true);
-        instruction.cycle
     }
 
-    fn sed(&mut self, instruction: &Instruction) -> u8 {
+    fn sed(&mut self) {
         self.processor_status.set_decimal(true);
-        instruction.cycle
     }
 
-    fn sei(&mut self, instruction: &Instruction) -> u8 {
+    fn sei(&mut self) {
         self.processor_status.set_interrupt_disable(true);
-        instruction.cycle
     }
 
-    fn sta(&mut self, instruction: &Instruction) -> u8 {
-        self.write_byte(&instruction.memory_addressing_mode, self.a);
-        instruction.cycle
+    fn shx(&mut self, instruction: &Instruction) {
+        self.store_and_high(instruction, self.x);
     }
 
-    fn stx(&mut self, instruction: &Instruction) -> u8 {
-        self.write_byte(&instruction.memory_addressing_mode, self.x);
-        instruction.cycle
+    fn shy(&mut self, instruction: &Instruction) {
+        self.store_and_high(instruction, self.y);
     }
 
-    fn sty(&mut self, instruction: &Instruction) -> u8 {
-        self.write_byte(&instruction.memory_addressing_mode, self.y);
-        instruction.cycle
+    fn slo(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, Self::shift_left);
+        self.a |= data;
+        self.set_negative_and_zero_process_status(self.a);
     }
 
-    fn tax(&mut self, instruction: &Instruction) -> u8 {
+    fn sre(&mut self, instruction: &Instruction) {
+        let data = self.read_modify_write(instruction, Self::shift_right);
+        self.a ^= data;
+        self.set_negative_and_zero_process_status(self.a);
+    }
+
+    fn sta(&mut self, instruction: &Instruction) {
+        self.write_byte(instruction, self.a);
+    }
+
+    fn stx(&mut self, instruction: &Instruction) {
+        self.write_byte(instruction, self.x);
+    }
+
+    fn sty(&mut self, instruction: &Instruction) {
+        self.write_byte(instruction, self.y);
+    }
+
+    fn stz(&mut self, instruction: &Instruction) {
+        self.write_byte(instruction, 0);
+    }
+
+    fn tas(&mut self, instruction: &Instruction) {
+        self.stack_pointer = self.a & self.x;
+        self.store_and_high(instruction, self.stack_pointer);
+    }
+
+    fn tax(&mut self) {
         self.x = self.a;
         self.set_negative_and_zero_process_status(self.x);
-        instruction.cycle
     }
 
-    fn tay(&mut self, instruction: &Instruction) -> u8 {
+    fn tay(&mut self) {
         self.y = self.a;
         self.set_negative_and_zero_process_status(self.y);
-        instruction.cycle
     }
 
-    fn tya(&mut self, instruction: &Instruction) -> u8 {
+    fn tya(&mut self) {
         self.a = self.y;
         self.set_negative_and_zero_process_status(self.a);
-        instruction.cycle
     }
 
-    fn txs(&mut self, instruction: &Instruction) -> u8 {
+    fn txs(&mut self) {
         self.stack_pointer = self.x;
-        instruction.cycle
     }
 
-    fn txa(&mut self, instruction: &Instruction) -> u8 {
+    fn txa(&mut self) {
         self.a = self.x;
         self.set_negative_and_zero_process_status(self.a);
-        instruction.cycle
     }
 
-    fn tsx(&mut self, instruction: &Instruction) -> u8 {
+    fn trb(&mut self, instruction: &Instruction) {
+        self.read_modify_write(instruction, |cpu, data| {
+            cpu.processor_status.set_zero(cpu.a & data == 0);
+            data & !cpu.a
+        });
+    }
+
+    fn tsb(&mut self, instruction: &Instruction) {
+        self.read_modify_write(instruction, |cpu, data| {
+            cpu.processor_status.set_zero(cpu.a & data == 0);
+            data | cpu.a
+        });
+    }
+
+    fn tsx(&mut self) {
         self.x = self.stack_pointer;
         self.set_negative_and_zero_process_status(self.x);
-        instruction.cycle
+    }
+
+    fn xaa(&mut self, instruction: &Instruction) {
+        let data = self.read_byte(instruction);
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & data;
+        self.set_negative_and_zero_process_status(self.a);
     }
 
     /*
     Addressing
     */
 
-    fn read_byte(&mut self, memory_addressing_mode: &MemoryAdressingMode) -> (u8, bool) {
-        let (byte, mut page_cross) = match memory_addressing_mode {
-            MemoryAdressingMode::Accumulator => (self.a, false),
-            MemoryAdressingMode::Immediate => (self.read_next_byte(), false),
+    fn read_byte(&mut self, instruction: &Instruction) -> u8 {
+        match instruction.memory_addressing_mode {
+            MemoryAdressingMode::Accumulator => self.a,
+            MemoryAdressingMode::Immediate => self.read_next_byte(),
             _ => {
-                let (addr, page_cross) = self.get_address(memory_addressing_mode);
-                (self.bus.read_byte(addr), page_cross)
+                let (addr, _page_cross) = self.operand_address(instruction);
+                let usage = match instruction.memory_addressing_mode {
+                    MemoryAdressingMode::IndirectX
+                    | MemoryAdressingMode::IndirectY
+                    | MemoryAdressingMode::ZeroPageIndirect => {
+                        PrgUsage::DATA | PrgUsage::INDIRECT_DATA
+                    }
+                    _ => PrgUsage::DATA,
+                };
+                self.bus.log_code_data(addr, usage);
+                self.read(addr)
             }
-        };
+        }
+    }
+
+    fn write_byte(&mut self, instruction: &Instruction, byte: u8) {
+        let (addr, _page_cross) = self.operand_address(instruction);
+        self.write(addr, byte);
+    }
 
-        if !matches!(
-            memory_addressing_mode,
+    /*
+    The indexed modes put the address on the bus before the high byte is fixed up.
+    Reads (Penalty::PageCross) only spend that cycle when the index crossed a page,
+    writes and read-modify-writes can't take back an access to the wrong page so
+    they always do.
+    */
+    fn operand_address(&mut self, instruction: &Instruction) -> (u16, bool) {
+        let (addr, page_cross) = self.get_address(&instruction.memory_addressing_mode);
+        if matches!(
+            instruction.memory_addressing_mode,
             MemoryAdressingMode::AbsoluteX
                 | MemoryAdressingMode::AbsoluteY
                 | MemoryAdressingMode::IndirectY
         ) {
-            page_cross = false
-        }
-        (byte, page_cross)
-    }
-
-    fn write_byte(&mut self, memory_addressing_mode: &MemoryAdressingMode, byte: u8) -> bool {
-        if cfg!(debug_assertions) {
-            println!(" Write Data: {:#04X?}", byte);
-        }
-        match memory_addressing_mode {
-            MemoryAdressingMode::Accumulator => {
-                self.a = byte;
-                self.set_negative_and_zero_process_status(self.a);
-                false
-            }
-            _ => {
-                let (addr, page_cross) = self.get_address(memory_addressing_mode);
-                self.bus.write_byte(addr, byte);
-                page_cross
+            match (instruction.penalty, page_cross) {
+                (Penalty::PageCross, false) => {}
+                (_, true) => {
+                    self.read(Self::unfixed_address(addr));
+                }
+                (_, false) => {
+                    self.read(addr);
+                }
             }
         }
+        (addr, page_cross)
     }
 
     fn get_address(&mut self, memory_addressing_mode: &MemoryAdressingMode) -> (u16, bool) {
@@ -740,6 +1038,7 @@ impl CPU {
             MemoryAdressingMode::ZeroPageY => self.zero_page_y_address(),
             MemoryAdressingMode::IndirectX => self.indirect_x_address(),
             MemoryAdressingMode::IndirectY => self.indirect_y_address(),
+            MemoryAdressingMode::ZeroPageIndirect => self.zero_page_indirect_address(),
             MemoryAdressingMode::Relative => panic!("Look up not supported for relative"),
             _ => panic!("Not Supported: {:?}", memory_addressing_mode),
         };
@@ -751,6 +1050,11 @@ impl CPU {
         (base_addr & 0xFF00) != (offset_addr & 0xFF00)
     }
 
+    // The address the CPU puts on the bus before carrying into the high byte
+    fn unfixed_address(addr: u16) -> u16 {
+        addr.wrapping_sub(0x100)
+    }
+
     fn absolute_address(&mut self) -> (u16, bool) {
         (self.read_next_word(), false)
     }
@@ -773,60 +1077,204 @@ impl CPU {
 
     fn zero_page_x_address(&mut self) -> (u16, bool) {
         let base_addr = self.read_next_byte();
-        let addr = base_addr.wrapping_add(self.x);
-        (
-            addr as u16,
-            Self::boundary_cross(base_addr as u16, addr as u16),
-        )
+        self.read(base_addr as u16);
+        (base_addr.wrapping_add(self.x) as u16, false)
     }
 
     fn zero_page_y_address(&mut self) -> (u16, bool) {
         let base_addr = self.read_next_byte();
-        let addr = base_addr.wrapping_add(self.y);
-        (
-            addr as u16,
-            Self::boundary_cross(base_addr as u16, addr as u16),
-        )
+        self.read(base_addr as u16);
+        (base_addr.wrapping_add(self.y) as u16, false)
     }
 
     fn indirect_x_address(&mut self) -> (u16, bool) {
         let base = self.read_next_byte();
-        let ptr: u8 = (base as u8).wrapping_add(self.x);
-        let lo = self.bus.read_byte(ptr as u16);
-        let hi = self.bus.read_byte(ptr.wrapping_add(1) as u16);
+        self.read(base as u16);
+        let ptr: u8 = base.wrapping_add(self.x);
+        let lo = self.read(ptr as u16);
+        let hi = self.read(ptr.wrapping_add(1) as u16);
         ((hi as u16) << 8 | (lo as u16), false)
     }
 
     fn indirect_y_address(&mut self) -> (u16, bool) {
         let base = self.read_next_byte();
-        let lo = self.bus.read_byte(base as u16);
-        let hi = self.bus.read_byte((base as u8).wrapping_add(1) as u16);
+        let lo = self.read(base as u16);
+        let hi = self.read(base.wrapping_add(1) as u16);
         let deref_base = (hi as u16) << 8 | (lo as u16);
         let addr = deref_base.wrapping_add(self.y as u16);
         (addr, Self::boundary_cross(deref_base, addr))
     }
 
+    fn zero_page_indirect_address(&mut self) -> (u16, bool) {
+        let base = self.read_next_byte();
+        let lo = self.read(base as u16);
+        let hi = self.read(base.wrapping_add(1) as u16);
+        ((hi as u16) << 8 | (lo as u16), false)
+    }
+
     /*
     Helpers
     */
 
+    fn read_modify_write<F>(&mut self, instruction: &Instruction, modify: F) -> u8
+    where
+        F: FnOnce(&mut Self, u8) -> u8,
+    {
+        let (address, _page_cross) = self.operand_address(instruction);
+        let data = self.read_data(address);
+        // The unmodified value is written back while the new one is computed,
+        // the 65C02 reads it a second time instead
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.read(address);
+        } else {
+            self.write(address, data);
+        }
+        let data = modify(self, data);
+        self.write(address, data);
+        data
+    }
+
+    /*
+    SHX, SHY, TAS and AHX store the register ANDed with the high byte of the base
+    address plus one. When the index crosses a page the high byte of the target
+    address is replaced with that same value.
+    */
+    fn store_and_high(&mut self, instruction: &Instruction, register: u8) {
+        let (addr, page_cross) = self.operand_address(instruction);
+        let index = match instruction.memory_addressing_mode {
+            MemoryAdressingMode::AbsoluteX => self.x,
+            _ => self.y,
+        };
+        let base_hi = (addr.wrapping_sub(index as u16) >> 8) as u8;
+        let value = register & base_hi.wrapping_add(1);
+        let addr = if page_cross {
+            (value as u16) << 8 | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        self.write(addr, value);
+    }
+
+    fn shift_left(&mut self, data: u8) -> u8 {
+        self.processor_status.set_carry(data >> 7 == 1);
+        data << 1
+    }
+
+    fn shift_right(&mut self, data: u8) -> u8 {
+        self.processor_status.set_carry(data & 0b0000_0001 == 1);
+        data >> 1
+    }
+
+    fn rotate_left(&mut self, data: u8) -> u8 {
+        let carry = self.processor_status.contains(ProcessorStatus::CARRY);
+        self.processor_status.set_carry(data >> 7 == 1);
+        data << 1 | carry as u8
+    }
+
+    fn rotate_right(&mut self, data: u8) -> u8 {
+        let carry = self.processor_status.contains(ProcessorStatus::CARRY);
+        self.processor_status.set_carry(data & 0b0000_0001 == 1);
+        data >> 1 | (carry as u8) << 7
+    }
+
     fn compare(&mut self, register: u8, value: u8) {
         self.processor_status.set_carry(register >= value);
         self.set_negative_and_zero_process_status(register.wrapping_sub(value))
     }
 
-    fn branch(&mut self, jump: bool) -> u8 {
-        let mut cycle_count = 0;
+    fn branch(&mut self, jump: bool) {
         let offset = self.read_next_byte() as i8;
         if jump {
-            cycle_count += 1;
             let old_pc = self.program_counter;
+            self.read(old_pc);
             self.program_counter = self.program_counter.wrapping_add(offset as u16);
-            if (old_pc & 0xFF00) != (self.program_counter & 0xFF00) {
-                cycle_count += 1;
+            if Self::boundary_cross(old_pc, self.program_counter) {
+                self.read((old_pc & 0xFF00) | (self.program_counter & 0x00FF));
+            }
+        }
+    }
+
+    fn decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode() && self.processor_status.contains(ProcessorStatus::DECIMAL)
+    }
+
+    fn add_with_carry(&mut self, data: u8) {
+        if !self.decimal_mode() {
+            self.a = self.add(self.a, data);
+            self.set_negative_and_zero_process_status(self.a);
+            return;
+        }
+
+        /*
+        BCD addition as laid out in Bruce Clark's decimal mode notes. The NMOS
+        chips take N and V from the result before the high nibble is adjusted
+        and Z from the binary sum, the 65C02 takes N and Z from the final result
+        at the cost of an extra cycle.
+        */
+        let carry = self.processor_status.contains(ProcessorStatus::CARRY) as i16;
+        let binary = self.a.wrapping_add(data).wrapping_add(carry as u8);
+        let mut lo = (self.a & 0x0f) as i16 + (data & 0x0f) as i16 + carry;
+        if lo >= 0x0a {
+            lo = ((lo + 0x06) & 0x0f) + 0x10;
+        }
+        let signed = (self.a & 0xf0) as i8 as i16 + (data & 0xf0) as i8 as i16 + lo;
+        let mut result = (self.a & 0xf0) as i16 + (data & 0xf0) as i16 + lo;
+        self.processor_status
+            .set_overflow(!(-128..=127).contains(&signed));
+        self.processor_status
+            .set_negative(ProcessorStatus::is_negative(result as u8));
+        if result >= 0xa0 {
+            result += 0x60;
+        }
+        self.processor_status.set_carry(result >= 0x100);
+        self.a = result as u8;
+
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.read(self.program_counter.wrapping_sub(1));
+            self.set_negative_and_zero_process_status(self.a);
+        } else {
+            self.processor_status.set_zero(binary == 0);
+        }
+    }
+
+    fn subtract_with_borrow(&mut self, data: u8) {
+        // The flags always come from the binary subtraction (except N and Z on the 65C02)
+        let a = self.a;
+        let carry = self.processor_status.contains(ProcessorStatus::CARRY) as i16;
+        self.a = self.add(self.a, !data);
+        self.set_negative_and_zero_process_status(self.a);
+        if !self.decimal_mode() {
+            return;
+        }
+
+        let lo = (a & 0x0f) as i16 - (data & 0x0f) as i16 + carry - 1;
+        let result = if self.variant == CpuVariant::Cmos65C02 {
+            let mut result = a as i16 - data as i16 + carry - 1;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if lo < 0 {
+                result -= 0x06;
+            }
+            result
+        } else {
+            let lo = if lo < 0 {
+                ((lo - 0x06) & 0x0f) - 0x10
+            } else {
+                lo
+            };
+            let mut result = (a & 0xf0) as i16 - (data & 0xf0) as i16 + lo;
+            if result < 0 {
+                result -= 0x60;
             }
+            result
+        };
+        self.a = result as u8;
+
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.read(self.program_counter.wrapping_sub(1));
+            self.set_negative_and_zero_process_status(self.a);
         }
-        cycle_count
     }
 
     fn add(&mut self, reg_value: u8, data: u8) -> u8 {
@@ -846,8 +1294,7 @@ impl CPU {
     }
 
     fn push(&mut self, byte: u8) {
-        self.bus
-            .write_byte((STACK as u16) + self.stack_pointer as u16, byte);
+        self.write(STACK + self.stack_pointer as u16, byte);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
@@ -861,35 +1308,108 @@ impl CPU {
     fn pop(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
 
-        let byte = self
-            .bus
-            .read_byte((STACK as u16) + self.stack_pointer as u16);
-        byte
+        self.read(STACK + self.stack_pointer as u16)
     }
 
     fn pop_word(&mut self) -> u16 {
         self.pop() as u16 | (self.pop() as u16) << 8
     }
 
-    pub(crate) fn reset_cpu(&mut self) {
-        self.processor_status = ProcessorStatus::default();
-        self.a = 0x0;
-        self.x = 0x0;
-        self.y = 0x0;
-        self.stack_pointer = 0xfd;
-        self.program_counter = self.bus.read_word(0xFFFC); // Part of the NES Spec
+    // Turns the whole machine off and on again, RAM is filled the way the bus was set up to
+    pub fn power_on(&mut self) {
+        self.bus.power_on();
+        self.power_on_cpu();
+    }
+
+    /*
+    The reset button: A, X, Y and the flags other than I are left alone and nothing
+    is cleared in RAM, which games use to tell a reset from a power on. The bus
+    resets the PPU registers that are wired to the reset line.
+    */
+    pub fn reset(&mut self) {
+        self.bus.reset();
+        self.reset_sequence();
+    }
+
+    fn power_on_cpu(&mut self) {
+        self.a = 0;
+        self.x = 0;
+        self.y = 0;
+        self.processor_status = ProcessorStatus::BREAK2;
+        // The reset sequence takes it down to $FD
+        self.stack_pointer = 0;
+        self.reset_sequence();
+    }
+
+    // An interrupt whose pushes are turned into reads, so the stack pointer goes
+    // down by three without anything being written
+    fn reset_sequence(&mut self) {
+        self.halted = false;
+        self.interrupt_sample = None;
+        self.interrupt_poll = None;
+        self.call_stack.clear();
+
+        self.read(self.program_counter);
+        self.read(self.program_counter);
+        for _ in 0..3 {
+            self.read(STACK + self.stack_pointer as u16);
+            self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        }
+        self.processor_status
+            .insert(ProcessorStatus::INTERRUPT_DISABLE);
+        self.program_counter = self.read_word(RESET_VECTOR);
+    }
+
+    // Every CPU bus access takes one cycle, the rest of the system is advanced
+    // before the access so devices see it at the right time
+    // The lines are sampled once the rest of the system has caught up with the cycle
+    fn tick(&mut self) {
+        self.cycle += 1;
+        self.bus.tick(1);
+        self.sample_interrupts();
     }
 
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        let data = self.bus.read(address);
+        self.breakpoints
+            .watch_cpu(MemoryOperation::Read, address, data);
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.tick();
+        self.bus.write(address, data);
+        self.breakpoints
+            .watch_cpu(MemoryOperation::Write, address, data);
+        self.cycle += self.bus.take_stall() as u64;
+    }
+
+    // Only used for vectors and pointers, so the bytes are logged as data
+    fn read_word(&mut self, address: u16) -> u16 {
+        let lo = self.read_data(address) as u16;
+        let hi = self.read_data(address.wrapping_add(1)) as u16;
+        hi << 8 | lo
+    }
+
+    fn read_data(&mut self, address: u16) -> u8 {
+        self.bus.log_code_data(address, PrgUsage::DATA);
+        self.read(address)
+    }
+
+    // Fetching the program doesn't go through read() so watchpoints don't see it
     fn read_next_byte(&mut self) -> u8 {
-        let byte = self.bus.read_byte(self.program_counter);
-        self.program_counter += 1;
+        self.bus.log_code_data(self.program_counter, PrgUsage::CODE);
+        self.tick();
+        let byte = self.bus.read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
         byte
     }
 
     fn read_next_word(&mut self) -> u16 {
-        let word = self.bus.read_word(self.program_counter);
-        self.program_counter += 2;
-        word
+        let lo = self.read_next_byte() as u16;
+        let hi = self.read_next_byte() as u16;
+        hi << 8 | lo
     }
 
     fn set_negative_and_zero_process_status(&mut self, int: u8) {
@@ -899,30 +1419,52 @@ impl CPU {
     }
 }
 
+impl CPU<MemoryBus> {
+    pub fn run_frame(&mut self) -> u64 {
+        let frame = self.bus.ppu.frame();
+        self.run_until(|cpu| cpu.bus.ppu.frame() != frame)
+    }
+
+    // Runs to the end of the current frame unless a breakpoint gets in the way
+    pub fn run_frame_until_break(&mut self) -> Option<Break> {
+        let frame = self.bus.ppu.frame();
+        self.run_until_break(|cpu| cpu.bus.ppu.frame() != frame)
+    }
+}
+
+// The tests' usual setup: the program in flat RAM and the PC on $8000
+#[cfg(test)]
+impl asm::Program {
+    pub(crate) fn cpu(&self) -> CPU<crate::bus::flat_ram::FlatRam> {
+        let mut bus = crate::bus::flat_ram::FlatRam::new();
+        self.load_into(&mut bus);
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x8000;
+        cpu
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use super::super::rom::{Mirroring, Rom};
+    use super::super::bus::flat_ram::FlatRam;
+    use super::asm::Program;
     use super::*;
+    use crate::assemble;
 
-    fn fake_rom(game_code: Vec<u8>) -> MemoryBus {
-        let mut prg_rom = vec![0; 0x8000];
-        let game_code_len = game_code.len();
-        prg_rom[0x00..game_code_len].copy_from_slice(&game_code);
-        let rom = Rom {
-            prg_rom,
-            chr_rom: vec![],
-            mapper: 0,
-            screen_mirroring: Mirroring::Horizontal,
-        };
-        let mut bus = MemoryBus::new(rom);
+    const OPCODE_KIL: u8 = 0x02;
 
-        let brk_handler = vec![OPCODE_EXIT; 4];
-        bus.write_interrupt_handler(InterruptType::BRK, 0x8000 - 0x10, brk_handler);
+    // Loads the program (at $8000 unless it says otherwise) with a BRK/IRQ handler
+    // that halts the CPU at $FFF0
+    fn fake_bus(program: Program) -> FlatRam {
+        let mut bus = FlatRam::new();
+        program.load_into(&mut bus);
+        bus.load(0xfff0, &[OPCODE_KIL; 4]);
+        bus.write_word(0xfffe, 0xfff0);
         bus
     }
 
-    pub fn start(cpu: &mut CPU) {
+    pub fn start(cpu: &mut CPU<FlatRam>) {
         cpu.start_with_callback(|_, _| {});
     }
 
@@ -953,13 +1495,16 @@ mod test {
             0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10, 0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb,
             0x60,
         ];
-        let mut cpu = CPU::new(fake_rom(game_code));
+        // Kept as the bytes it was published as
+        let mut bus = fake_bus(assemble!(".org $8000"));
+        bus.load(0x8000, &game_code);
+        let mut cpu = CPU::new(bus);
         start(&mut cpu);
     }
 
     #[test]
     fn test_adc() {
-        let mut cpu = CPU::new(fake_rom(vec![0x69, 0x10, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("adc #$10", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
         cpu.a = 0x00;
@@ -972,7 +1517,7 @@ mod test {
 
     #[test]
     fn test_adc_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0x69, 0x10, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("adc #$10", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
         cpu.a = 0xff;
@@ -985,7 +1530,7 @@ mod test {
 
     #[test]
     fn test_asl() {
-        let mut cpu = CPU::new(fake_rom(vec![0x0a, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("asl a", "brk")));
 
         cpu.program_counter = 0x8000;
         cpu.a = 0b1111_1111;
@@ -997,7 +1542,7 @@ mod test {
 
     #[test]
     fn test_asl_no_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0x0a, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("asl a", "brk")));
 
         cpu.program_counter = 0x8000;
         cpu.a = 0b0111_1111;
@@ -1009,7 +1554,12 @@ mod test {
 
     #[test]
     fn test_bcc_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0x90, 0x02, 0x69, 0x01, 0x69, 0x01, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!(
+            "bcc skip",
+            "adc #$01",
+            "skip: adc #$01",
+            "brk"
+        )));
 
         cpu.program_counter = 0x8000;
         cpu.processor_status.set_carry(true);
@@ -1019,7 +1569,12 @@ mod test {
 
     #[test]
     fn test_bcc_no_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0x90, 0x02, 0x69, 0x01, 0x69, 0x01, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!(
+            "bcc skip",
+            "adc #$01",
+            "skip: adc #$01",
+            "brk"
+        )));
 
         cpu.program_counter = 0x8000;
         cpu.processor_status.set_carry(false);
@@ -1028,7 +1583,12 @@ mod test {
     }
     #[test]
     fn test_bcs_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0xb0, 0x02, 0x69, 0x01, 0x69, 0x01, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!(
+            "bcs skip",
+            "adc #$01",
+            "skip: adc #$01",
+            "brk"
+        )));
 
         cpu.program_counter = 0x8000;
         cpu.processor_status.set_carry(true);
@@ -1038,7 +1598,12 @@ mod test {
 
     #[test]
     fn test_bcs_no_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0xb0, 0x02, 0x69, 0x01, 0x69, 0x01, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!(
+            "bcs skip",
+            "adc #$01",
+            "skip: adc #$01",
+            "brk"
+        )));
 
         cpu.program_counter = 0x8000;
         cpu.processor_status.set_carry(false);
@@ -1048,10 +1613,10 @@ mod test {
 
     #[test]
     fn test_bit_zero() {
-        let mut cpu = CPU::new(fake_rom(vec![0x2c, 0xaa, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("bit $aa", "brk")));
         cpu.program_counter = 0x8000;
         cpu.a = 0b0111_1111;
-        cpu.bus.write_byte(0xaa, 0b0000_0000);
+        cpu.bus.write(0xaa, 0b0000_0000);
         start(&mut cpu);
 
         assert!(cpu.processor_status.contains(ProcessorStatus::ZERO));
@@ -1061,10 +1626,10 @@ mod test {
 
     #[test]
     fn test_bit_not_zero_overflow_carry() {
-        let mut cpu = CPU::new(fake_rom(vec![0x2c, 0xaa, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("bit $aa", "brk")));
         cpu.program_counter = 0x8000;
         cpu.a = 0b0111_1111;
-        cpu.bus.write_byte(0xaa, 0b1100_0001);
+        cpu.bus.write(0xaa, 0b1100_0001);
         start(&mut cpu);
 
         assert!(!cpu.processor_status.contains(ProcessorStatus::ZERO));
@@ -1074,7 +1639,7 @@ mod test {
 
     #[test]
     fn test_inx_overflow() {
-        let mut cpu = CPU::new(fake_rom(vec![0xe8, 0xe8, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("inx", "inx", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
         cpu.x = 0xff;
@@ -1083,9 +1648,186 @@ mod test {
         assert_eq!(cpu.x, 1);
     }
 
+    #[test]
+    fn test_lax() {
+        let mut cpu = CPU::new(fake_bus(assemble!("lax $10", "brk")));
+        cpu.bus.write(0x10, 0x80);
+        cpu.program_counter = 0x8000;
+        start(&mut cpu);
+
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(cpu.x, 0x80);
+        assert!(cpu.processor_status.contains(ProcessorStatus::NEGATIVE));
+    }
+
+    #[test]
+    fn test_sax() {
+        let mut cpu = CPU::new(fake_bus(assemble!("sax $10", "brk")));
+        cpu.program_counter = 0x8000;
+        cpu.a = 0b1100_1100;
+        cpu.x = 0b1010_1010;
+        start(&mut cpu);
+
+        assert_eq!(cpu.bus.peek(0x10), 0b1000_1000);
+    }
+
+    #[test]
+    fn test_dcp() {
+        let mut cpu = CPU::new(fake_bus(assemble!("dcp $10", "brk")));
+        cpu.bus.write(0x10, 0x06);
+        cpu.program_counter = 0x8000;
+        cpu.a = 0x05;
+        start(&mut cpu);
+
+        assert_eq!(cpu.bus.peek(0x10), 0x05);
+        assert!(cpu.processor_status.contains(ProcessorStatus::ZERO));
+        assert!(cpu.processor_status.contains(ProcessorStatus::CARRY));
+    }
+
+    #[test]
+    fn test_isb() {
+        let mut cpu = CPU::new(fake_bus(assemble!("sec", "isb $10", "brk")));
+        cpu.bus.write(0x10, 0x01);
+        cpu.program_counter = 0x8000;
+        cpu.a = 0x05;
+        start(&mut cpu);
+
+        assert_eq!(cpu.bus.peek(0x10), 0x02);
+        assert_eq!(cpu.a, 0x03);
+        assert!(cpu.processor_status.contains(ProcessorStatus::CARRY));
+    }
+
+    #[test]
+    fn test_slo() {
+        let mut cpu = CPU::new(fake_bus(assemble!("slo $10", "brk")));
+        cpu.bus.write(0x10, 0b1000_0001);
+        cpu.program_counter = 0x8000;
+        cpu.a = 0b0000_0001;
+        start(&mut cpu);
+
+        assert_eq!(cpu.bus.peek(0x10), 0b0000_0010);
+        assert_eq!(cpu.a, 0b0000_0011);
+        assert!(cpu.processor_status.contains(ProcessorStatus::CARRY));
+    }
+
+    #[test]
+    fn test_rol() {
+        let mut cpu = CPU::new(fake_bus(assemble!("rol a", "brk")));
+        cpu.program_counter = 0x8000;
+        cpu.a = 0b0000_0000;
+        cpu.processor_status.set_carry(true);
+        start(&mut cpu);
+
+        assert_eq!(cpu.a, 0b0000_0001);
+        assert!(!cpu.processor_status.contains(ProcessorStatus::CARRY));
+    }
+
+    #[test]
+    fn test_kil_halts() {
+        let mut cpu = CPU::new(fake_bus(assemble!("lda #$01", "kil", "lda #$02")));
+        cpu.program_counter = 0x8000;
+        start(&mut cpu);
+
+        assert!(cpu.halted);
+        assert_eq!(cpu.a, 0x01);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut cpu = CPU::new(fake_bus(assemble!("lda #$05", "sta $0200")));
+        cpu.program_counter = 0x8000;
+
+        let step = cpu.step();
+        assert_eq!(step.instruction.instruction_type, InstructionType::LDA);
+        assert_eq!(step.cycles, 2);
+        assert_eq!(step.interrupt, None);
+        assert_eq!(cpu.a, 0x05);
+
+        let step = cpu.step();
+        assert_eq!(step.instruction.instruction_type, InstructionType::STA);
+        assert_eq!(step.cycles, 4);
+        assert_eq!(cpu.bus.peek(0x0200), 0x05);
+        // On top of the 7 of the reset sequence
+        assert_eq!(cpu.cycle, 7 + 6);
+    }
+
+    #[test]
+    fn test_step_cycles_per_bus_access() {
+        let mut cpu = CPU::new(fake_bus(assemble!(
+            "       lda $02ff,x ; page cross",
+            "       sta $0200,x ; always pays the fix up",
+            "       inc $0200,x",
+            "       pha",
+            "       pla",
+            "       jsr sub",
+            "       nop",
+            "sub:   rts",
+        )));
+        cpu.program_counter = 0x8000;
+        cpu.x = 0x01;
+
+        let cycles: Vec<u16> = (0..7).map(|_| cpu.step().cycles).collect();
+        assert_eq!(cycles, vec![5, 5, 7, 3, 4, 6, 6]);
+        assert_eq!(cpu.program_counter, 0x800E);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = CPU::new(fake_bus(assemble!("bcc *+2", "bcs *+2", "bcc $7ff6")));
+        cpu.program_counter = 0x8000;
+        cpu.processor_status.set_carry(false);
+
+        assert_eq!(cpu.step().cycles, 3); // taken
+        assert_eq!(cpu.step().cycles, 2); // not taken
+        assert_eq!(cpu.step().cycles, 4); // taken across a page
+        assert_eq!(cpu.program_counter, 0x7ff6);
+    }
+
+    #[test]
+    fn test_run_for_cycles() {
+        let mut cpu = CPU::new(fake_bus(assemble!("loop: inx", "jmp loop")));
+        cpu.program_counter = 0x8000;
+
+        let cycles = cpu.run_for_cycles(50);
+        assert_eq!(cycles, 50);
+        assert_eq!(cpu.x, 10);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut cpu = CPU::new(fake_bus(assemble!("loop: inx", "jmp loop")));
+        cpu.program_counter = 0x8000;
+
+        cpu.run_until(|cpu| cpu.x == 3);
+        assert_eq!(cpu.x, 3);
+        assert_eq!(cpu.program_counter, 0x8001);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let rom = assemble!("loop: jmp loop").rom().unwrap();
+        let mut cpu = CPU::new(MemoryBus::new(rom));
+        cpu.program_counter = 0x8000;
+
+        // The reset sequence already ran 7 cycles into the frame
+        let cycles = cpu.run_frame();
+        assert_eq!(cpu.bus.ppu.frame(), 1);
+        assert!((29_771..29_777).contains(&cycles));
+    }
+
+    #[test]
+    fn test_run_stops_when_halted() {
+        let mut cpu = CPU::new(fake_bus(assemble!("inx", "kil")));
+        cpu.program_counter = 0x8000;
+
+        let cycles = cpu.run_for_cycles(1_000);
+        assert!(cpu.halted);
+        assert_eq!(cycles, 4);
+    }
+
     #[test]
     fn test_read_next_byte() {
-        let mut cpu = CPU::new(fake_rom(vec![0x06, 0x12]));
+        let mut cpu = CPU::new(fake_bus(assemble!("asl $12")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
@@ -1100,24 +1842,21 @@ mod test {
 
     #[test]
     fn test_ld() {
-        let mut cpu = CPU::new(fake_rom(vec![0xa9, 0xc5, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("lda #$c5", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
         start(&mut cpu);
 
         assert_eq!(cpu.a, 0xc5);
-        assert_eq!(cpu.processor_status.contains(ProcessorStatus::ZERO), false);
-        assert_eq!(
-            cpu.processor_status.contains(ProcessorStatus::NEGATIVE),
-            true
-        );
+        assert!(!cpu.processor_status.contains(ProcessorStatus::ZERO));
+        assert!(cpu.processor_status.contains(ProcessorStatus::NEGATIVE));
     }
 
     #[test]
     fn test_ld_from_memory() {
-        let mut cpu = CPU::new(fake_rom(vec![0xa5, 0x10, 0x00]));
-        cpu.bus.write_byte(0x10, 0x55);
+        let mut cpu = CPU::new(fake_bus(assemble!("lda $10", "brk")));
+        cpu.bus.write(0x10, 0x55);
         cpu.program_counter = 0x8000;
 
         start(&mut cpu);
@@ -1127,54 +1866,54 @@ mod test {
 
     #[test]
     fn test_ld_zero() {
-        let mut cpu = CPU::new(fake_rom(vec![0xa9, 0x00, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("lda #$00", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
         start(&mut cpu);
-        assert_eq!(cpu.processor_status.contains(ProcessorStatus::ZERO), true)
+        assert!(cpu.processor_status.contains(ProcessorStatus::ZERO))
     }
 
     #[test]
     fn test_tax_zero() {
-        let mut cpu = CPU::new(fake_rom(vec![0xaa, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("tax", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
         cpu.a = 0x00;
         start(&mut cpu);
         assert_eq!(cpu.x, cpu.a);
-        assert_eq!(cpu.processor_status.contains(ProcessorStatus::ZERO), true)
+        assert!(cpu.processor_status.contains(ProcessorStatus::ZERO))
     }
 
     #[test]
     fn test_tax() {
-        let mut cpu = CPU::new(fake_rom(vec![0xaa, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("tax", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
         cpu.a = 0x01;
         start(&mut cpu);
         assert_eq!(cpu.x, cpu.a);
-        assert_eq!(cpu.processor_status.contains(ProcessorStatus::ZERO), false)
+        assert!(!cpu.processor_status.contains(ProcessorStatus::ZERO))
     }
 
     #[test]
     fn test_sta() {
-        let mut cpu = CPU::new(fake_rom(vec![0x85, 0x04, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("sta $04", "brk")));
         // Set the ROM start to default
         cpu.program_counter = 0x8000;
 
         cpu.a = 0x10;
         start(&mut cpu);
 
-        assert_eq!(cpu.a, cpu.bus.read_byte(0x04));
+        assert_eq!(cpu.a, cpu.bus.peek(0x04));
     }
 
     #[test]
 
     fn test_stack() {
-        let mut cpu = CPU::new(fake_rom(vec![0x85, 0x04, 0x00]));
+        let mut cpu = CPU::new(fake_bus(assemble!("sta $04", "brk")));
         cpu.push(0x10);
         assert_eq!(cpu.pop(), 0x10);
 
@@ -1186,8 +1925,220 @@ mod test {
         cpu.push(0x56);
         cpu.push(0x78);
 
-        for i in vec![0x78, 0x56, 0x34, 0x20] {
+        for i in [0x78, 0x56, 0x34, 0x20] {
             assert_eq!(cpu.pop(), i);
         }
     }
+
+    #[test]
+    fn test_irq_masked_by_interrupt_disable() {
+        let mut cpu = CPU::new(fake_bus(assemble!("nop", "nop", "nop")));
+        cpu.program_counter = 0x8000;
+        cpu.processor_status.set_interrupt_disable(true);
+        cpu.bus.set_irq(true);
+
+        assert_eq!(cpu.step().interrupt, None);
+        assert_eq!(cpu.program_counter, 0x8001);
+
+        // Like the I flag changing on the last cycle: the next instruction runs first
+        cpu.processor_status.set_interrupt_disable(false);
+        assert_eq!(cpu.step().interrupt, None);
+        let step = cpu.step();
+        assert_eq!(step.interrupt, Some(InterruptType::IRQ));
+        // 7 for the interrupt sequence, 2 for the KIL in the handler
+        assert_eq!(step.cycles, 9);
+        assert!(cpu
+            .processor_status
+            .contains(ProcessorStatus::INTERRUPT_DISABLE));
+        assert!(cpu.halted);
+        let pushed =
+            ProcessorStatus::from_bits_truncate(cpu.bus.peek(STACK + cpu.stack_pointer as u16 + 1));
+        assert!(!pushed.contains(ProcessorStatus::BREAK));
+        assert_eq!(cpu.read_word(STACK + cpu.stack_pointer as u16 + 2), 0x8002);
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut cpu = CPU::new(fake_bus(assemble!("brk")));
+        cpu.program_counter = 0x8000;
+
+        let step = cpu.step();
+        assert_eq!(step.cycles, 7);
+        assert_eq!(step.interrupt, None);
+        assert_eq!(cpu.program_counter, 0xfff0);
+        assert!(!cpu.processor_status.contains(ProcessorStatus::BREAK));
+        let pushed =
+            ProcessorStatus::from_bits_truncate(cpu.bus.peek(STACK + cpu.stack_pointer as u16 + 1));
+        assert!(pushed.contains(ProcessorStatus::BREAK | ProcessorStatus::BREAK2));
+        assert_eq!(cpu.read_word(STACK + cpu.stack_pointer as u16 + 2), 0x8002);
+
+        start(&mut cpu);
+        assert!(cpu.halted);
+    }
+
+    #[test]
+    fn test_cli_delays_irq() {
+        let mut cpu = CPU::new(fake_bus(assemble!("cli", "nop", "nop")));
+        cpu.program_counter = 0x8000;
+        cpu.processor_status.set_interrupt_disable(true);
+        cpu.bus.set_irq(true);
+
+        assert_eq!(cpu.step().interrupt, None);
+        // The poll after CLI still sees I set, so one more instruction runs first
+        assert_eq!(cpu.step().interrupt, None);
+        assert_eq!(cpu.program_counter, 0x8002);
+        assert_eq!(cpu.step().interrupt, Some(InterruptType::IRQ));
+    }
+
+    // Flat RAM that raises an NMI on one bus cycle
+    struct NmiOnCycle {
+        ram: FlatRam,
+        cycle: u64,
+    }
+
+    impl Bus for NmiOnCycle {
+        fn read(&mut self, address: u16) -> u8 {
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data)
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.ram.peek(address)
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.ram.tick(cycles);
+            if self.ram.cycles() == self.cycle {
+                self.ram.trigger_nmi();
+            }
+        }
+
+        fn nmi_pending(&self) -> bool {
+            self.ram.nmi_pending()
+        }
+
+        fn acknowledge_nmi(&mut self) {
+            self.ram.acknowledge_nmi()
+        }
+    }
+
+    #[test]
+    fn test_nmi_on_last_cycle() {
+        let run = |cycle| {
+            let ram = fake_bus(assemble!("nop", "nop", "nop"));
+            let mut cpu = CPU::new(NmiOnCycle { ram, cycle: 0 });
+            cpu.program_counter = 0x8000;
+            // Counting from the first NOP, after the power-on sequence
+            cpu.bus.cycle = cpu.bus.ram.cycles() + cycle;
+            (cpu.step().interrupt, cpu.step().interrupt)
+        };
+
+        // Raised on the first NOP's first cycle it's taken right after it, on its
+        // last cycle only after the next one
+        assert_eq!(run(1), (None, Some(InterruptType::NMI)));
+        assert_eq!(run(2), (None, None));
+        assert_eq!(run(3), (None, None));
+    }
+
+    #[test]
+    fn test_sei_lets_pending_irq_through() {
+        let mut cpu = CPU::new(fake_bus(assemble!("sei", "nop")));
+        cpu.program_counter = 0x8000;
+        cpu.processor_status.set_interrupt_disable(false);
+        cpu.bus.set_irq(true);
+
+        // SEI's first cycle still sees I clear
+        assert_eq!(cpu.step().interrupt, None);
+        assert_eq!(cpu.step().interrupt, Some(InterruptType::IRQ));
+    }
+
+    #[test]
+    fn test_decimal_mode_by_variant() {
+        let program = assemble!("sed", "clc", "lda #$58", "adc #$46");
+
+        // The 65C02 has no KIL, so run up to the end of the program instead
+        let mut cpu = CPU::new(fake_bus(program.clone()));
+        cpu.program_counter = 0x8000;
+        cpu.run_until(|cpu| cpu.program_counter == 0x8006);
+        assert_eq!(cpu.a, 0x9e);
+        assert!(!cpu.processor_status.contains(ProcessorStatus::CARRY));
+
+        for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02] {
+            let mut cpu = CPU::new_with_variant(fake_bus(program.clone()), variant);
+            cpu.program_counter = 0x8000;
+            cpu.run_until(|cpu| cpu.program_counter == 0x8006);
+            assert_eq!(cpu.a, 0x04);
+            assert!(cpu.processor_status.contains(ProcessorStatus::CARRY));
+        }
+    }
+
+    #[test]
+    fn test_decimal_sbc() {
+        let program =
+            assemble!("sed", "sec", "lda #$46", "sbc #$12", "tax", "lda #$12", "sbc #$21", "kil");
+        let mut cpu = CPU::new_with_variant(fake_bus(program), CpuVariant::Nmos6502);
+        cpu.program_counter = 0x8000;
+        start(&mut cpu);
+
+        assert_eq!(cpu.x, 0x34);
+        assert_eq!(cpu.a, 0x91);
+        assert!(!cpu.processor_status.contains(ProcessorStatus::CARRY));
+    }
+
+    #[test]
+    fn test_jmp_indirect_by_variant() {
+        // The pointer's high byte is either at $8000 or at $8100
+        let program = assemble!("jmp ($80ff)", ".org $80ff", ".word $1234");
+
+        let mut cpu = CPU::new(fake_bus(program.clone()));
+        cpu.program_counter = 0x8000;
+        assert_eq!(cpu.step().cycles, 5);
+        assert_eq!(cpu.program_counter, 0x6c34);
+
+        let mut cpu = CPU::new_with_variant(fake_bus(program), CpuVariant::Cmos65C02);
+        cpu.program_counter = 0x8000;
+        assert_eq!(cpu.step().cycles, 6);
+        assert_eq!(cpu.program_counter, 0x1234);
+    }
+
+    #[test]
+    fn test_65c02_opcodes() {
+        let program = assemble!(CpuVariant::Cmos65C02;
+            "       lda #$ff",
+            "       sta $10",
+            "       stz $10",
+            "       .byte $a7   ; LAX $10 on NMOS parts, a one cycle NOP here",
+            "       bra skip",
+            "       inc a",
+            "skip:  dec a",
+            "       phx",
+        );
+        let mut cpu = CPU::new_with_variant(fake_bus(program), CpuVariant::Cmos65C02);
+        cpu.program_counter = 0x8000;
+        assert!(!cpu.variant.has_illegal_opcodes());
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            cpu.step().instruction.instruction_type,
+            InstructionType::STZ
+        );
+        assert_eq!(cpu.bus.peek(0x10), 0x00);
+
+        let step = cpu.step();
+        assert_eq!(step.instruction.instruction_type, InstructionType::NOP);
+        assert_eq!(step.cycles, 1);
+        assert_eq!(cpu.a, 0xff);
+
+        assert_eq!(cpu.step().cycles, 3);
+        assert_eq!(cpu.program_counter, 0x800a);
+        assert_eq!(
+            cpu.step().instruction.instruction_type,
+            InstructionType::DEC
+        );
+        assert_eq!(cpu.a, 0xfe);
+    }
 }
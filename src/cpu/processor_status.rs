@@ -16,6 +16,7 @@ bitflags! {
 }
 
 impl ProcessorStatus {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         carry: bool,
         zero: bool,
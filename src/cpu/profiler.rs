@@ -0,0 +1,418 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::{self, Write},
+};
+
+use super::{
+    call_stack::{CallFrame, FrameKind},
+    instructions::{Instruction, Penalty},
+    interrupt::InterruptType,
+    CPU,
+};
+use crate::bus::Bus;
+
+/*
+Counts where the cycles go: per instruction address, per subroutine and per frame.
+It works off the shadow call stack, so a routine is everything between a JSR (or an
+interrupt) and the RTS (or RTI) that returns from it. Call record() after every step,
+for example from start_with_callback:
+
+    let mut profiler = Profiler::new(&cpu);
+    cpu.start_with_callback(|cpu, _| profiler.record(cpu));
+
+Exclusive cycles are spent in the routine itself, inclusive ones also count the
+routines it called. Per address it also counts the cycles page crossings and
+taken branches added on top of the instruction's base count.
+*/
+
+// What the CPU was servicing: an NMI handler, an IRQ (or BRK) handler or neither
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    Main,
+    Nmi,
+    Irq,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextCycles {
+    pub main: u64,
+    pub nmi: u64,
+    pub irq: u64,
+}
+
+impl ContextCycles {
+    pub fn total(&self) -> u64 {
+        self.main + self.nmi + self.irq
+    }
+
+    fn add(&mut self, context: Context, cycles: u64) {
+        match context {
+            Context::Main => self.main += cycles,
+            Context::Nmi => self.nmi += cycles,
+            Context::Irq => self.irq += cycles,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoutineProfile {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+// One entry of a folded stack: how the routine was entered and where it starts
+type StackEntry = (FrameKind, u16);
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    per_address: HashMap<u16, u64>,
+    penalties: HashMap<u16, u64>,
+    routines: HashMap<u16, RoutineProfile>,
+    frames: BTreeMap<u64, ContextCycles>,
+    stacks: HashMap<Vec<StackEntry>, u64>,
+    total: ContextCycles,
+
+    // Where the CPU stood at the last record(), the next step runs from there
+    program_counter: u16,
+    instruction: Option<&'static Instruction>,
+    path: Vec<CallFrame>,
+    frame: u64,
+    cycle: u64,
+    // Saves allocating a stack key for every instruction
+    stack: Vec<StackEntry>,
+}
+
+impl Profiler {
+    pub fn new<B: Bus>(cpu: &CPU<B>) -> Self {
+        let mut profiler = Self::default();
+        profiler.sync(cpu);
+        profiler
+    }
+
+    // Puts the cycles the CPU ran since the last call down to the instruction it ran
+    pub fn record<B: Bus>(&mut self, cpu: &CPU<B>) {
+        let cycles = cpu.cycle - self.cycle;
+        let frames = cpu.call_stack.frames();
+        let common = self
+            .path
+            .iter()
+            .zip(frames)
+            .take_while(|(ours, theirs)| ours == theirs)
+            .count();
+
+        // An NMI or IRQ taken before the instruction means the handler's first
+        // instruction is what actually ran
+        let interrupt = frames.get(common).filter(|frame| {
+            common == self.path.len()
+                && frame.caller == self.program_counter
+                && matches!(
+                    frame.kind,
+                    FrameKind::Interrupt(InterruptType::NMI | InterruptType::IRQ)
+                )
+        });
+        let path = std::mem::take(&mut self.path);
+        match interrupt {
+            Some(handler) => self.attribute(handler.target, &frames[..=common], cycles),
+            None => {
+                self.attribute(self.program_counter, &path, cycles);
+                self.count_penalty(cycles);
+            }
+        }
+        self.path = path;
+
+        for frame in &frames[common..] {
+            self.routines.entry(frame.target).or_default().calls += 1;
+        }
+        self.sync(cpu);
+    }
+
+    fn sync<B: Bus>(&mut self, cpu: &CPU<B>) {
+        self.program_counter = cpu.program_counter;
+        self.instruction =
+            Some(&cpu.variant.instruction_set()[cpu.bus.peek(cpu.program_counter) as usize]);
+        self.path.clear();
+        self.path.extend_from_slice(cpu.call_stack.frames());
+        self.frame = cpu.bus.ppu_frame().unwrap_or(0);
+        self.cycle = cpu.cycle;
+    }
+
+    fn attribute(&mut self, address: u16, path: &[CallFrame], cycles: u64) {
+        *self.per_address.entry(address).or_default() += cycles;
+
+        let context = path
+            .iter()
+            .rev()
+            .find_map(|frame| match frame.kind {
+                FrameKind::Interrupt(InterruptType::NMI) => Some(Context::Nmi),
+                FrameKind::Interrupt(_) => Some(Context::Irq),
+                FrameKind::Subroutine => None,
+            })
+            .unwrap_or(Context::Main);
+        self.total.add(context, cycles);
+        self.frames
+            .entry(self.frame)
+            .or_default()
+            .add(context, cycles);
+
+        if let Some(innermost) = path.last() {
+            self.routines.entry(innermost.target).or_default().exclusive += cycles;
+        }
+        for (depth, frame) in path.iter().enumerate() {
+            // Recursion only counts once
+            if path[..depth]
+                .iter()
+                .all(|outer| outer.target != frame.target)
+            {
+                self.routines.entry(frame.target).or_default().inclusive += cycles;
+            }
+        }
+
+        self.stack.clear();
+        self.stack
+            .extend(path.iter().map(|frame| (frame.kind, frame.target)));
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(total) => *total += cycles,
+            None => {
+                self.stacks.insert(self.stack.clone(), cycles);
+            }
+        }
+    }
+
+    fn count_penalty(&mut self, cycles: u64) {
+        if let Some(instruction) = self.instruction {
+            let extra = cycles.saturating_sub(instruction.cycle as u64);
+            if instruction.penalty != Penalty::None && extra > 0 {
+                *self.penalties.entry(self.program_counter).or_default() += extra;
+            }
+        }
+    }
+
+    pub fn total(&self) -> ContextCycles {
+        self.total
+    }
+
+    pub fn cycles_at(&self, address: u16) -> u64 {
+        self.per_address.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn penalty_cycles_at(&self, address: u16) -> u64 {
+        self.penalties.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn routine(&self, address: u16) -> Option<RoutineProfile> {
+        self.routines.get(&address).copied()
+    }
+
+    // Most expensive (inclusive) first
+    pub fn routines(&self) -> Vec<(u16, RoutineProfile)> {
+        let mut routines: Vec<_> = self
+            .routines
+            .iter()
+            .map(|(&address, &profile)| (address, profile))
+            .collect();
+        routines.sort_by_key(|&(address, profile)| (std::cmp::Reverse(profile.inclusive), address));
+        routines
+    }
+
+    // Busiest instruction addresses first
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut addresses: Vec<_> = self
+            .per_address
+            .iter()
+            .map(|(&address, &cycles)| (address, cycles))
+            .collect();
+        addresses.sort_by_key(|&(address, cycles)| (std::cmp::Reverse(cycles), address));
+        addresses
+    }
+
+    // Keyed by the PPU frame counter, always frame 0 on a bus without a PPU
+    pub fn frames(&self) -> &BTreeMap<u64, ContextCycles> {
+        &self.frames
+    }
+
+    // One "main;$C000;NMI@$D000 1234" line per distinct call stack, what flamegraph.pl
+    // and inferno take as input. Lines are sorted so two runs diff cleanly.
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let mut line = String::from("main");
+                for (kind, address) in stack {
+                    match kind {
+                        FrameKind::Subroutine => line.push_str(&format!(";${:04X}", address)),
+                        FrameKind::Interrupt(interrupt) => {
+                            line.push_str(&format!(";{:?}@${:04X}", interrupt, address))
+                        }
+                    }
+                }
+                format!("{} {}", line, cycles)
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    // A text summary with the top routines and instruction addresses
+    pub fn report(&self, limit: usize) -> String {
+        let total = self.total.total().max(1);
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+        let mut lines = vec![
+            format!(
+                "{} cycles over {} frame(s): main {} ({:.1}%), NMI {} ({:.1}%), IRQ {} ({:.1}%)",
+                self.total.total(),
+                self.frames.len(),
+                self.total.main,
+                percent(self.total.main),
+                self.total.nmi,
+                percent(self.total.nmi),
+                self.total.irq,
+                percent(self.total.irq)
+            ),
+            String::new(),
+            format!(
+                "{:<8}{:>10}{:>14}{:>8}{:>14}{:>8}",
+                "Routine", "Calls", "Inclusive", "%", "Exclusive", "%"
+            ),
+        ];
+        for (address, profile) in self.routines().into_iter().take(limit) {
+            lines.push(format!(
+                "${:04X}   {:>10}{:>14}{:>8.1}{:>14}{:>8.1}",
+                address,
+                profile.calls,
+                profile.inclusive,
+                percent(profile.inclusive),
+                profile.exclusive,
+                percent(profile.exclusive)
+            ));
+        }
+        lines.push(String::new());
+        lines.push(format!(
+            "{:<8}{:>14}{:>8}{:>10}",
+            "Address", "Cycles", "%", "Penalty"
+        ));
+        for (address, cycles) in self.hot_spots().into_iter().take(limit) {
+            lines.push(format!(
+                "${:04X}   {:>14}{:>8.1}{:>10}",
+                address,
+                cycles,
+                percent(cycles),
+                self.penalty_cycles_at(address)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Display for Profiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report(20))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+    use crate::bus::flat_ram::FlatRam;
+
+    fn profile(program: crate::cpu::asm::Program) -> (CPU<FlatRam>, Profiler) {
+        let mut cpu = program.cpu();
+        if let Some(nmi) = program.symbol("nmi") {
+            cpu.bus.write_word(0xfffa, nmi);
+        }
+        let mut profiler = Profiler::new(&cpu);
+        cpu.start_with_callback(|cpu, _| profiler.record(cpu));
+        (cpu, profiler)
+    }
+
+    #[test]
+    fn test_routines() {
+        let program = assemble!(
+            "        jsr outer", // 6
+            "        jsr inner", // 6
+            "        kil",
+            "outer:  jsr inner", // 6
+            "        nop",       // 2
+            "        rts",       // 6
+            "inner:  ldx #$02",  // 2
+            "loop:   dex",       // 2 x 2
+            "        bne loop",  // 3 + 2
+            "        rts",       // 6
+        );
+        let outer = program.symbol("outer").unwrap();
+        let inner = program.symbol("inner").unwrap();
+        let (cpu, profiler) = profile(program);
+        assert_eq!(profiler.routine(inner).unwrap().calls, 2);
+        assert_eq!(
+            profiler.routine(inner).unwrap(),
+            RoutineProfile {
+                calls: 2,
+                inclusive: 2 * 17,
+                exclusive: 2 * 17,
+            }
+        );
+        assert_eq!(
+            profiler.routine(outer).unwrap(),
+            RoutineProfile {
+                calls: 1,
+                inclusive: 14 + 17,
+                exclusive: 14,
+            }
+        );
+        // The 7 cycles of the reset sequence ran before the profiler started
+        assert_eq!(profiler.total().total(), cpu.cycle - 7);
+        assert_eq!(profiler.cycles_at(inner + 2), 2 * 2 * 2);
+        // The BNE is taken once per call
+        assert_eq!(profiler.penalty_cycles_at(inner + 3), 2);
+        assert_eq!(profiler.penalty_cycles_at(inner + 2), 0);
+        assert_eq!(profiler.routines()[0].0, inner);
+
+        let mut folded = vec![];
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            [
+                format!("main {}", cpu.cycle - 7 - 14 - 2 * 17),
+                "main;$8007 14".to_owned(),
+                "main;$8007;$800C 17".to_owned(),
+                "main;$800C 17".to_owned(),
+                String::new()
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_nmi_context() {
+        let program = assemble!("main:  jmp main", "nmi:   nop", "       rti",);
+        let mut cpu = program.cpu();
+        cpu.bus.write_word(0xfffa, program.symbol("nmi").unwrap());
+        let mut profiler = Profiler::new(&cpu);
+
+        // Raised before the JMP, it's taken once the JMP has run
+        cpu.bus.trigger_nmi();
+        cpu.step();
+        profiler.record(&cpu);
+        cpu.step();
+        profiler.record(&cpu);
+        while cpu.call_stack.depth() > 0 {
+            cpu.step();
+            profiler.record(&cpu);
+        }
+
+        let total = profiler.total();
+        assert_eq!(total.main, 3);
+        // 7 to take the NMI, 2 for the NOP and 6 for the RTI
+        assert_eq!(total.nmi, 7 + 2 + 6);
+        assert_eq!(profiler.cycles_at(0x8003), 7 + 2);
+        assert_eq!(profiler.routine(0x8003).unwrap().calls, 1);
+        assert_eq!(profiler.frames()[&0], total);
+        assert!(profiler.report(5).contains("NMI 15 (83.3%)"));
+    }
+}
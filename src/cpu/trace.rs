@@ -0,0 +1,247 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use super::{disasm::disassemble, CPU};
+use crate::bus::Bus;
+
+/*
+Formats the instruction the CPU is about to run the way nestest.log (and
+Nintendulator) does:
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+The PPU column is left out when the bus has no PPU.
+*/
+pub fn trace_line<B: Bus>(cpu: &CPU<B>) -> String {
+    let line = disassemble(&cpu.bus, cpu.program_counter, cpu.variant);
+    let bytes: Vec<String> = line
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    // nestest.log puts a * in front of the unofficial opcodes
+    let marker = if !line.instruction.is_official() {
+        '*'
+    } else {
+        ' '
+    };
+    let ppu = match cpu.bus.ppu_position() {
+        Some((scanline, dot)) => format!(" PPU:{:>3},{:>3}", scanline, dot),
+        None => String::new(),
+    };
+
+    format!(
+        "{:04X}  {:<8} {}{:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}{} CYC:{}",
+        cpu.program_counter,
+        bytes.join(" "),
+        marker,
+        line.annotated(cpu),
+        cpu.a,
+        cpu.x,
+        cpu.y,
+        cpu.processor_status.bits(),
+        cpu.stack_pointer,
+        ppu,
+        cpu.cycle
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceTrigger {
+    Immediately,
+    Never,
+    ProgramCounter(u16),
+    // Fires once the CPU has run for at least this many cycles
+    Cycle(u64),
+}
+
+impl TraceTrigger {
+    fn fired<B>(&self, cpu: &CPU<B>) -> bool {
+        match *self {
+            TraceTrigger::Immediately => true,
+            TraceTrigger::Never => false,
+            TraceTrigger::ProgramCounter(address) => cpu.program_counter == address,
+            TraceTrigger::Cycle(cycle) => cpu.cycle >= cycle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TraceState {
+    Waiting,
+    Logging,
+    Stopped,
+}
+
+// Writes a trace line for every instruction between the start and stop triggers.
+// The line for the instruction that fires the stop trigger is not written.
+pub struct TraceLogger<W: Write> {
+    writer: W,
+    start: TraceTrigger,
+    stop: TraceTrigger,
+    state: TraceState,
+}
+
+impl TraceLogger<BufWriter<File>> {
+    pub fn to_file<P: AsRef<Path>>(
+        path: P,
+        start: TraceTrigger,
+        stop: TraceTrigger,
+    ) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), start, stop))
+    }
+}
+
+impl<W: Write> TraceLogger<W> {
+    pub fn new(writer: W, start: TraceTrigger, stop: TraceTrigger) -> Self {
+        Self {
+            writer,
+            start,
+            stop,
+            state: TraceState::Waiting,
+        }
+    }
+
+    // Call before each step with the CPU about to run the traced instruction
+    pub fn log<B: Bus>(&mut self, cpu: &CPU<B>) -> io::Result<()> {
+        if self.state == TraceState::Waiting && self.start.fired(cpu) {
+            self.state = TraceState::Logging;
+        }
+        if self.state == TraceState::Logging && self.stop.fired(cpu) {
+            self.state = TraceState::Stopped;
+            self.writer.flush()?;
+        }
+        if self.state == TraceState::Logging {
+            writeln!(self.writer, "{}", trace_line(cpu))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_logging(&self) -> bool {
+        self.state == TraceState::Logging
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    // 1 based, like an editor would show it
+    pub line: usize,
+    // None when that trace ended first
+    pub ours: Option<String>,
+    pub reference: Option<String>,
+}
+
+// Finds the first line where two traces differ. Trailing whitespace (and the CRLF
+// endings of nestest.log) is ignored.
+pub fn first_divergence<A: BufRead, R: BufRead>(
+    ours: A,
+    reference: R,
+) -> io::Result<Option<Divergence>> {
+    let mut ours = ours.lines();
+    let mut reference = reference.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        let our_line = ours.next().transpose()?;
+        let reference_line = reference.next().transpose()?;
+        match (&our_line, &reference_line) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) if a.trim_end() == b.trim_end() => continue,
+            _ => {
+                return Ok(Some(Divergence {
+                    line,
+                    ours: our_line.map(|line| line.trim_end().to_owned()),
+                    reference: reference_line.map(|line| line.trim_end().to_owned()),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::processor_status::ProcessorStatus;
+    use super::*;
+    use crate::bus::flat_ram::FlatRam;
+
+    fn cpu(code: &[u8]) -> CPU<FlatRam> {
+        let mut bus = FlatRam::new();
+        bus.load(0xc000, code);
+        CPU::new_with_state(
+            bus,
+            0xc000,
+            0xfd,
+            0,
+            0,
+            0,
+            ProcessorStatus::from_bits_truncate(0x24),
+            7,
+        )
+    }
+
+    #[test]
+    fn test_trace_line() {
+        // JMP $C5F5; *NOP $A9
+        let cpu = cpu(&[0x4c, 0xf5, 0xc5, 0x04, 0xa9]);
+        assert_eq!(
+            trace_line(&cpu),
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+        );
+
+        let mut cpu = cpu;
+        cpu.program_counter = 0xc003;
+        assert_eq!(
+            trace_line(&cpu),
+            "C003  04 A9    *NOP $A9 = 00                    A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+        );
+    }
+
+    #[test]
+    fn test_logger_triggers() {
+        // INX; INX; INX; JMP $C000
+        let mut cpu = cpu(&[0xe8, 0xe8, 0xe8, 0x4c, 0x00, 0xc0]);
+        let mut logger = TraceLogger::new(
+            vec![],
+            TraceTrigger::ProgramCounter(0xc001),
+            TraceTrigger::ProgramCounter(0xc003),
+        );
+        for _ in 0..6 {
+            logger.log(&cpu).unwrap();
+            cpu.step();
+        }
+        assert!(!logger.is_logging());
+
+        let trace = String::from_utf8(logger.into_inner().unwrap()).unwrap();
+        let addresses: Vec<&str> = trace.lines().map(|line| &line[..4]).collect();
+        assert_eq!(addresses, ["C001", "C002"]);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let reference = "A\r\nB\r\nC\r\n";
+        assert_eq!(
+            first_divergence("A\nB\nC\n".as_bytes(), reference.as_bytes()).unwrap(),
+            None
+        );
+        assert_eq!(
+            first_divergence("A\nX\nC\n".as_bytes(), reference.as_bytes()).unwrap(),
+            Some(Divergence {
+                line: 2,
+                ours: Some("X".to_owned()),
+                reference: Some("B".to_owned()),
+            })
+        );
+        assert_eq!(
+            first_divergence("A\nB\n".as_bytes(), reference.as_bytes())
+                .unwrap()
+                .map(|divergence| divergence.ours),
+            Some(None)
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use super::instructions::{
+    instruction_set::{INSTRUCTION_SET, INSTRUCTION_SET_65C02},
+    Instruction,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CpuVariant {
+    // The NES CPU: an NMOS 6502 core with the decimal mode adder disconnected
+    #[default]
+    Ricoh2A03,
+    Nmos6502,
+    // The original CMOS part, without the Rockwell/WDC bit instructions, WAI or STP
+    Cmos65C02,
+}
+
+impl CpuVariant {
+    pub fn instruction_set(&self) -> &'static [Instruction; 256] {
+        match self {
+            CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => &INSTRUCTION_SET,
+            CpuVariant::Cmos65C02 => &INSTRUCTION_SET_65C02,
+        }
+    }
+
+    pub fn has_decimal_mode(&self) -> bool {
+        *self != CpuVariant::Ricoh2A03
+    }
+
+    pub fn has_jmp_indirect_bug(&self) -> bool {
+        *self != CpuVariant::Cmos65C02
+    }
+
+    // The 65C02 turned every unofficial NMOS opcode into a NOP or one of its new instructions
+    pub fn has_illegal_opcodes(&self) -> bool {
+        *self != CpuVariant::Cmos65C02
+    }
+}
@@ -2,14 +2,14 @@ mod apu;
 pub mod bus;
 pub mod cpu;
 mod gamepad;
-mod ppu;
+pub mod ppu;
 pub mod rom;
 
 #[macro_use]
 extern crate bitflags;
 
 use bus::MemoryBus;
-use cpu::CPU;
+use cpu::{variant::CpuVariant, CPU};
 
 use rand::Rng;
 use rom::Rom;
@@ -50,6 +50,15 @@ fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
             } => {
                 cpu.bus.write_byte(0xff, 0x64);
             }
+            // The reset button keeps RAM, power cycling refills it
+            Event::KeyDown {
+                keycode: Some(Keycode::R),
+                ..
+            } => cpu.reset(),
+            Event::KeyDown {
+                keycode: Some(Keycode::P),
+                ..
+            } => cpu.power_on(),
             _ => { /* do nothing */ }
         }
     }
@@ -110,8 +119,8 @@ pub fn start_game_from_rom(rom: Rom) {
         .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
         .unwrap();
 
-    let mut cpu = CPU::new(MemoryBus::new(rom));
-    cpu.reset_cpu();
+    // The snake demo is plain 6502 code rather than an NES program
+    let mut cpu = CPU::new_with_variant(MemoryBus::new(rom), CpuVariant::Nmos6502);
 
     let mut screen_state = [0_u8; 32 * 3 * 32];
     let mut rng = rand::thread_rng();
@@ -131,4 +140,7 @@ pub fn start_game_from_rom(rom: Rom) {
 
         ::std::thread::sleep(std::time::Duration::new(0, 70_000));
     });
+
+    // Only a KIL opcode gets us here, which usually means the program went off the rails
+    eprintln!("CPU halted: {}\n{}", cpu, cpu.backtrace());
 }
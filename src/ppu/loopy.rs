@@ -0,0 +1,169 @@
+/*
+The PPU's internal scroll and address registers, named after the person who worked
+them out. $2000, $2005 and $2006 all write into the same ones, which is what lets
+games change the scroll mid-frame through $2006.
+
+v  the current VRAM address, what $2007 reads and writes and what rendering fetches
+   from:  yyy NN YYYYY XXXXX  (fine Y, nametable, coarse Y, coarse X)
+t  the same layout, where the address or scroll being written is put together
+x  fine X scroll, 3 bits
+w  which of the two writes to $2005/$2006 comes next, shared by both and reset by
+   reading $2002
+*/
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Loopy {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+}
+
+impl Loopy {
+    // $2000, the nametable bits
+    pub fn write_control(&mut self, data: u8) {
+        self.t = self.t & !0x0c00 | ((data & 0b11) as u16) << 10;
+    }
+
+    // $2005, X then Y
+    pub fn write_scroll(&mut self, data: u8) {
+        let data = data as u16;
+        if !self.w {
+            self.t = self.t & !0x001f | data >> 3;
+            self.x = (data & 0x07) as u8;
+        } else {
+            self.t = self.t & !0x73e0 | (data & 0x07) << 12 | (data & 0xf8) << 2;
+        }
+        self.w = !self.w;
+    }
+
+    // $2006, high byte then low byte, v only changes on the second write
+    pub fn write_address(&mut self, data: u8) {
+        let data = data as u16;
+        if !self.w {
+            // The top bit of fine Y doesn't exist in the address and gets cleared
+            self.t = self.t & 0x00ff | (data & 0x3f) << 8;
+        } else {
+            self.t = self.t & 0xff00 | data;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    pub fn reset_latch(&mut self) {
+        self.w = false;
+    }
+
+    pub fn address(&self) -> u16 {
+        self.v & 0x3fff
+    }
+
+    // After a $2007 access outside of rendering
+    pub fn increment(&mut self, amount: u8) {
+        self.v = self.v.wrapping_add(amount as u16) & 0x7fff;
+    }
+
+    // Past the 32nd tile the next nametable over
+    pub fn increment_coarse_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v = (self.v & !0x001f) ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // Fine Y, then coarse Y, which wraps at row 30 into the nametable below (rows
+    // 30 and 31 are the attribute table and wrap without switching nametables)
+    pub fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let coarse_y = match (self.v & 0x03e0) >> 5 {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        self.v = self.v & !0x03e0 | coarse_y << 5;
+    }
+
+    pub fn copy_horizontal(&mut self) {
+        self.v = self.v & !0x041f | self.t & 0x041f;
+    }
+
+    pub fn copy_vertical(&mut self) {
+        self.v = self.v & !0x7be0 | self.t & 0x7be0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address() {
+        let mut loopy = Loopy::default();
+        loopy.write_address(0x30);
+        assert_eq!(loopy.address(), 0);
+        loopy.write_address(0x16);
+        assert_eq!(loopy.address(), 0x3016);
+
+        loopy.increment(1);
+        assert_eq!(loopy.address(), 0x3017);
+        loopy.increment(32);
+        assert_eq!(loopy.address(), 0x3037);
+
+        // Only 14 bits make it out
+        loopy.write_address(0xff);
+        loopy.write_address(0xff);
+        assert_eq!(loopy.address(), 0x3fff);
+    }
+
+    #[test]
+    fn test_scroll_shares_the_latch() {
+        let mut loopy = Loopy::default();
+        loopy.write_control(0b10);
+        loopy.write_scroll(0x7d);
+        assert_eq!((loopy.t, loopy.x, loopy.w), (0x080f, 0x05, true));
+        loopy.write_scroll(0x5e);
+        assert_eq!(loopy.t, 0x696f);
+
+        // The mid-frame split trick: $2006, $2005, $2005, $2006
+        loopy.write_address(0x04);
+        loopy.write_scroll(0x3e);
+        loopy.write_scroll(0x7d);
+        loopy.write_address(0xef);
+        assert_eq!((loopy.v, loopy.x, loopy.w), (0x64ef, 0x05, false));
+    }
+
+    #[test]
+    fn test_increments() {
+        let mut loopy = Loopy {
+            v: 0x001f,
+            ..Loopy::default()
+        };
+        loopy.increment_coarse_x();
+        assert_eq!(loopy.v, 0x0400);
+
+        // Fine Y 7 of row 29 goes to the top of the nametable below
+        loopy.v = 0x73a0;
+        loopy.increment_y();
+        assert_eq!(loopy.v, 0x0800);
+        loopy.v = 0x73e0;
+        loopy.increment_y();
+        assert_eq!(loopy.v, 0x0000);
+        loopy.v = 0x1000;
+        loopy.increment_y();
+        assert_eq!(loopy.v, 0x2000);
+
+        loopy.t = 0x7fff;
+        loopy.copy_horizontal();
+        assert_eq!(loopy.v, 0x241f);
+        loopy.copy_vertical();
+        assert_eq!(loopy.v, 0x7fff);
+    }
+}
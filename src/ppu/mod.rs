@@ -1,22 +1,19 @@
-mod address;
-mod registers;
+mod loopy;
+pub mod registers;
 pub mod render;
-mod scroll;
 
-use crate::{cpu::interrupt::InterruptType, ppu::render::SYSTEM_PALLETE, rom::Mirroring};
-use address::Address;
+use crate::{
+    bus::{cdl::ChrUsage, AddressSpace, MemoryAccess, MemoryOperation, RamInit},
+    ppu::render::SYSTEM_PALLETE,
+    rom::Mirroring,
+};
+use loopy::Loopy;
 use registers::{Control, Mask, Status};
-use scroll::Scroll;
 
-use self::render::Frame;
+use self::render::{Background, Frame, SpriteLine, PRE_RENDER_LINE, VBLANK_LINE};
 
 #[derive(Debug)]
 pub enum PPUAddress {
-    CHRROM(u16),
-    RAM(u16),
-    PaletteTable(u16),
-
-    // Registers
     Controller,
     Mask,
     Status,
@@ -25,18 +22,16 @@ pub enum PPUAddress {
     Scroll,
     Address,
     Data,
-    OAMDMA,
 }
 
 #[derive(Debug)]
 pub enum PPUValue {
     Byte(u8),
-    Buffer(&'static [u8; 256]),
 }
 
-impl Into<PPUValue> for u8 {
-    fn into(self) -> PPUValue {
-        PPUValue::Byte(self)
+impl From<u8> for PPUValue {
+    fn from(val: u8) -> Self {
+        PPUValue::Byte(val)
     }
 }
 
@@ -44,41 +39,49 @@ impl From<PPUValue> for u8 {
     fn from(data: PPUValue) -> Self {
         match data {
             PPUValue::Byte(value) => value,
-            _ => panic!("cannot convert to byte"),
         }
     }
 }
 
-impl From<PPUValue> for &'static [u8; 256] {
-    fn from(data: PPUValue) -> Self {
-        match data {
-            PPUValue::Buffer(value) => value,
-            _ => panic!("cannot convert to buffer"),
+// The eight registers repeat every 8 bytes up to $3FFF
+impl From<u16> for PPUAddress {
+    fn from(value: u16) -> Self {
+        match value & 0x0007 {
+            0 => Self::Controller, // write-only
+            1 => Self::Mask,       // write-only
+            2 => Self::Status,
+            3 => Self::OAMAddress, // write-only
+            4 => Self::OAMData,
+            5 => Self::Scroll,  // write-only
+            6 => Self::Address, // write-only
+            _ => Self::Data,
         }
     }
 }
 
-impl From<u16> for PPUAddress {
-    fn from(value: u16) -> Self {
-        match value {
-            0..=0x1fff => Self::CHRROM(value),
-            0x2000 => Self::Controller, // write-only
-            0x2001 => Self::Mask,       // write-only
-            0x2002 => Self::Status,
-            0x2003 => Self::OAMAddress, // write-only
-            0x2004 => Self::OAMData,
-            0x2005 => Self::Scroll,  // write-only
-            0x2006 => Self::Address, // write-only
-            0x2007 => Self::Data,
-            0x2009..=0x2fff => Self::RAM(value),
-            0x3000..=0x3eff => panic!(
-                "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
-                value
-            ),
-            0x3f00..=0x3fff => Self::PaletteTable(value),
-            0x4014 => Self::OAMDMA, // write-only
-            _ => panic!("Unsupported address {}", value),
-        }
+// What $2007 reaches: the PPU's own address space, where there are no registers
+#[derive(Debug)]
+enum VramTarget {
+    Chr(u16),
+    Nametable(u16),
+    Palette(u16),
+}
+
+fn vram_target(address: u16) -> VramTarget {
+    match address & 0x3fff {
+        0..=0x1fff => VramTarget::Chr(address),
+        0x2000..=0x3eff => VramTarget::Nametable(address),
+        _ => VramTarget::Palette(address),
+    }
+}
+
+// $3F10, $3F14, $3F18 and $3F1C are the same entries as $3F00, $3F04, $3F08 and $3F0C
+fn palette_index(address: u16) -> usize {
+    let index = (address & 0x1f) as usize;
+    if index & 0x13 == 0x10 {
+        index & 0x0f
+    } else {
+        index
     }
 }
 
@@ -90,46 +93,182 @@ pub struct PPU {
     palette_table: [u8; 32],
     // 0x3f00 - 0x2000
     vram: [u8; 2048],
-    // 0x2000 - 0x0000
+    // 0x2000 - 0x0000, 8KB of CHR RAM when the cartridge has no CHR ROM
     chr_rom: Vec<u8>,
+    chr_ram: bool,
 
     oam_addr: u8,
     oam_data: [u8; 256],
     mirroring: Mirroring,
     buffer: u8,
-    pub(crate) nmi_interrupt: Option<InterruptType>,
+    // The PPU's own data bus. Every register write leaves its value here and reads
+    // of write only registers (or of the bits a register doesn't drive) return it.
+    // On hardware it decays to 0 after a second or so, that isn't emulated.
+    io_latch: u8,
 
     // PPU Registers
     ctrl: Control,
     mask: Mask,
     status: Status,
-    scroll: Scroll,
-    address: Address,
+    // Scroll and VRAM address, shared by $2000, $2005, $2006 and the renderer
+    loopy: Loopy,
 
     // screen
     scanline: u16,
     cycles: usize,
+    frame: u64,
+    // A $2002 read right around the start of vblank: one dot early the flag never goes
+    // up this frame, on the dot itself or the next the flag reads set but the NMI is lost
+    suppress_vblank: bool,
+    nmi_suppressed: bool,
+    dropped_write: Option<u16>,
+
+    // Rendering state, see render.rs
+    background: Background,
+    sprites: SpriteLine,
+    next_sprites: SpriteLine,
+    frame_buffer: Frame,
+
+    // VRAM and OAM accesses, only kept while a debugger watches them
+    access_log: Option<Vec<MemoryAccess>>,
+    // The CHR half of the code/data log, while one is running
+    chr_usage: Option<Vec<ChrUsage>>,
 }
 
 impl PPU {
     pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        let chr_rom = if chr_ram { vec![0; 0x2000] } else { chr_rom };
         Self {
             chr_rom,
+            chr_ram,
             vram: [0; 2048],
             oam_data: [0; 64 * 4],
             oam_addr: 0,
             palette_table: [0; 32],
             mirroring,
-            address: Address::default(),
+            loopy: Loopy::default(),
             ctrl: Control::default(),
             status: Status::default(),
             mask: Mask::default(),
-            scroll: Scroll::default(),
             buffer: 0,
+            io_latch: 0,
             scanline: 0,
             cycles: 0,
-            nmi_interrupt: None,
+            frame: 0,
+            suppress_vblank: false,
+            nmi_suppressed: false,
+            dropped_write: None,
+            background: Background::default(),
+            sprites: SpriteLine::default(),
+            next_sprites: SpriteLine::default(),
+            frame_buffer: Frame::default(),
+            access_log: None,
+            chr_usage: None,
+        }
+    }
+
+    // Only 6 bits of each palette entry exist, VRAM is left alone
+    pub fn power_on(&mut self, ram_init: RamInit) {
+        ram_init.fill(&mut self.oam_data);
+        ram_init.fill(&mut self.palette_table);
+        for entry in self.palette_table.iter_mut() {
+            *entry &= 0x3f;
         }
+        self.status = Status::default();
+        self.oam_addr = 0;
+        self.io_latch = 0;
+        self.loopy = Loopy::default();
+        self.scanline = 0;
+        self.cycles = 0;
+        self.suppress_vblank = false;
+        self.nmi_suppressed = false;
+        self.reset();
+    }
+
+    // The reset line clears the registers games write to set up a frame, but not
+    // the status, OAM or any of the memories, and rendering carries on from where it was
+    pub fn reset(&mut self) {
+        self.ctrl = Control::default();
+        self.mask = Mask::default();
+        // The address in v survives, the scroll being put together in t doesn't
+        self.loopy.t = 0;
+        self.loopy.x = 0;
+        self.loopy.reset_latch();
+        self.buffer = 0;
+    }
+
+    pub fn record_accesses(&mut self, enabled: bool) {
+        match (enabled, &self.access_log) {
+            (true, None) => self.access_log = Some(vec![]),
+            (false, _) => self.access_log = None,
+            _ => {}
+        }
+    }
+
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.access_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn log_access(
+        &mut self,
+        space: AddressSpace,
+        operation: MemoryOperation,
+        address: u16,
+        value: u8,
+    ) {
+        if let Some(log) = &mut self.access_log {
+            log.push(MemoryAccess {
+                space,
+                operation,
+                address,
+                value,
+            });
+        }
+    }
+
+    // The code/data log only covers CHR ROM
+    pub(crate) fn chr_rom_len(&self) -> usize {
+        if self.chr_ram {
+            0
+        } else {
+            self.chr_rom.len()
+        }
+    }
+
+    // The bus reports $2007 writes that CHR ROM dropped, with their PPU address
+    pub fn take_dropped_write(&mut self) -> Option<u16> {
+        self.dropped_write.take()
+    }
+
+    pub(crate) fn set_chr_usage(&mut self, usage: Option<Vec<ChrUsage>>) {
+        self.chr_usage = usage;
+    }
+
+    pub(crate) fn chr_usage(&self) -> Option<&[ChrUsage]> {
+        self.chr_usage.as_deref()
+    }
+
+    pub(crate) fn take_chr_usage(&mut self) -> Option<Vec<ChrUsage>> {
+        self.chr_usage.take()
+    }
+
+    fn log_chr(&mut self, address: usize, usage: ChrUsage) {
+        if let Some(entry) = self
+            .chr_usage
+            .as_mut()
+            .and_then(|chr_usage| chr_usage.get_mut(address))
+        {
+            *entry |= usage;
+        }
+    }
+
+    // What has been drawn so far, the picture is complete when tick says the frame is
+    pub fn frame_buffer(&self) -> &Frame {
+        &self.frame_buffer
     }
 
     pub fn read_register<T>(&mut self, register: T) -> PPUValue
@@ -137,19 +276,62 @@ impl PPU {
         T: Into<PPUAddress>,
     {
         let register = register.into();
-        match register {
+        let data = match register {
             PPUAddress::Controller
             | PPUAddress::Mask
             | PPUAddress::OAMAddress
             | PPUAddress::Scroll
-            | PPUAddress::Address
-            | PPUAddress::OAMDMA => {
-                panic!("{:?} is a write only register", register)
+            | PPUAddress::Address => self.io_latch,
+            PPUAddress::OAMData => {
+                let data = self.oam_data[self.oam_addr as usize];
+                self.log_access(
+                    AddressSpace::Oam,
+                    MemoryOperation::Read,
+                    self.oam_addr as u16,
+                    data,
+                );
+                data
             }
-            PPUAddress::OAMData => self.oam_data[self.oam_addr as usize].into(),
-            PPUAddress::Data => self.read_data(),
-            PPUAddress::Status => self.status.bits().into(),
-            _ => panic!("register not provided: {:?}", register),
+            PPUAddress::Data => self.read_data().into(),
+            // Only the top three bits are status, the rest is whatever was on the bus
+            PPUAddress::Status => {
+                let data = self.status.bits() & 0xe0 | self.io_latch & 0x1f;
+                if self.scanline == VBLANK_LINE {
+                    match self.cycles {
+                        1 => self.suppress_vblank = true,
+                        2 | 3 => self.nmi_suppressed = true,
+                        _ => {}
+                    }
+                }
+                self.status.reset_vblank_status();
+                self.loopy.reset_latch();
+                data
+            }
+        };
+        self.io_latch = data;
+        data.into()
+    }
+
+    pub fn io_latch(&self) -> u8 {
+        self.io_latch
+    }
+
+    // What a read of the register would return, without touching any latches
+    pub fn peek_register<T>(&self, register: T) -> u8
+    where
+        T: Into<PPUAddress>,
+    {
+        match register.into() {
+            PPUAddress::OAMData => self.oam_data[self.oam_addr as usize],
+            PPUAddress::Status => self.status.bits() & 0xe0 | self.io_latch & 0x1f,
+            PPUAddress::Data => match self.loopy.address() {
+                0x3f00..=0x3fff => {
+                    self.palette_table[palette_index(self.loopy.address())] & 0x3f
+                        | self.io_latch & 0xc0
+                }
+                _ => self.buffer,
+            },
+            _ => self.io_latch,
         }
     }
 
@@ -157,7 +339,7 @@ impl PPU {
         assert!(bank <= 1);
 
         let mut frame = Frame::default();
-        let bank = (bank * 0x1000) as usize;
+        let bank = bank * 0x1000;
 
         let tile = &self.chr_rom[(bank + tile_n * 16)..=(bank + tile_n * 16 + 15)];
 
@@ -167,8 +349,8 @@ impl PPU {
 
             for x in (0..=7).rev() {
                 let value = (1 & upper) << 1 | (1 & lower);
-                upper = upper >> 1;
-                lower = lower >> 1;
+                upper >>= 1;
+                lower >>= 1;
                 let rgb = match value {
                     0 => SYSTEM_PALLETE[0x01],
                     1 => SYSTEM_PALLETE[0x23],
@@ -188,74 +370,120 @@ impl PPU {
         T: Into<PPUAddress>,
     {
         let register = register.into();
+        let PPUValue::Byte(value) = data;
+        self.io_latch = value;
         match register {
+            // Enabling NMI while already in vblank raises the line, the bus picks up the edge
             PPUAddress::Controller => {
-                let before_nmi_status = self.ctrl.generate_vblank_nmi();
-                self.ctrl.update(data.into());
-                if !before_nmi_status
-                    && self.ctrl.generate_vblank_nmi()
-                    && self.status.is_in_vblank()
-                {
-                    self.nmi_interrupt = Some(InterruptType::NMI);
-                }
+                self.ctrl.update(value);
+                self.loopy.write_control(value);
             }
             PPUAddress::Mask => self.mask.update(data.into()),
-            PPUAddress::Status => panic!("status is a r/o register but was written to!"),
+            // Read only, the write only reaches the latch
+            PPUAddress::Status => {}
             PPUAddress::OAMAddress => self.oam_addr = data.into(),
             PPUAddress::OAMData => {
-                self.oam_data[self.oam_addr as usize] = data.into();
+                let data = data.into();
+                self.oam_data[self.oam_addr as usize] = data;
+                self.log_access(
+                    AddressSpace::Oam,
+                    MemoryOperation::Write,
+                    self.oam_addr as u16,
+                    data,
+                );
                 self.oam_addr = self.oam_addr.wrapping_add(1)
             }
-            PPUAddress::Scroll => self.scroll.write(data.into()),
-            PPUAddress::Address => self.address.update(data.into()),
+            PPUAddress::Scroll => self.loopy.write_scroll(value),
+            PPUAddress::Address => self.loopy.write_address(value),
             PPUAddress::Data => self.write_data(data),
-            PPUAddress::OAMDMA => {
-                let data: &'static [u8; 256] = data.into();
-                for x in data.iter() {
-                    self.oam_data[self.oam_addr as usize] = *x;
-                    self.oam_addr = self.oam_addr.wrapping_add(1);
-                }
-            }
-            _ => panic!("register not provided: {:?}", register),
+        }
+    }
+
+    // While rendering, a $2007 access bumps v the way the fetches do instead
+    fn increment_address(&mut self) {
+        if self.rendering_enabled() && (self.scanline < 240 || self.scanline == 261) {
+            self.loopy.increment_coarse_x();
+            self.loopy.increment_y();
+        } else {
+            self.loopy.increment(self.ctrl.vram_addr_increment());
         }
     }
 
     fn read_data(&mut self) -> PPUValue {
-        let address = self.address.get().into();
-        self.address.increment(self.ctrl.vram_addr_increment());
+        let vram_address = self.loopy.address();
+        let address = vram_target(vram_address);
+        self.increment_address();
 
         match address {
-            PPUAddress::CHRROM(value) => {
+            VramTarget::Chr(value) => {
                 let result = self.buffer;
                 self.buffer = self.chr_rom[value as usize];
+                self.log_chr(value as usize, ChrUsage::READ);
+                self.log_access(
+                    AddressSpace::Ppu,
+                    MemoryOperation::Read,
+                    vram_address,
+                    self.buffer,
+                );
                 PPUValue::Byte(result)
             }
-            PPUAddress::PaletteTable(value) => PPUValue::Byte(self.palette_table[value as usize]),
-            PPUAddress::RAM(value) => {
+            VramTarget::Palette(value) => {
+                // Palette entries are 6 bits, the top two come off the PPU bus
+                let data = self.palette_table[palette_index(value)] & 0x3f | self.io_latch & 0xc0;
+                self.log_access(AddressSpace::Ppu, MemoryOperation::Read, vram_address, data);
+                PPUValue::Byte(data)
+            }
+            VramTarget::Nametable(value) => {
                 let result = self.buffer;
                 self.buffer = self.vram[self.mirror_vram_addr(value) as usize];
+                self.log_access(
+                    AddressSpace::Ppu,
+                    MemoryOperation::Read,
+                    vram_address,
+                    self.buffer,
+                );
                 PPUValue::Byte(result)
             }
-            PPUAddress::Status => {
-                let data = self.status.snapshot();
-                self.status.reset_vblank_status();
-                self.address.reset_latch();
-                self.scroll.reset_latch();
-                PPUValue::Byte(data)
-            }
-            _ => panic!("Read to Write Only register: {:?}", address),
         }
     }
 
     fn write_data(&mut self, data: PPUValue) {
-        let address: PPUAddress = self.address.get().into();
-        self.address.increment(self.ctrl.vram_addr_increment());
+        let vram_address = self.loopy.address();
+        let address = vram_target(vram_address);
+        self.increment_address();
 
         match address {
-            PPUAddress::RAM(addr) => {
-                self.vram[self.mirror_vram_addr(addr) as usize] = data.into();
+            VramTarget::Chr(addr) if self.chr_ram => {
+                let data = data.into();
+                self.chr_rom[addr as usize] = data;
+                self.log_access(
+                    AddressSpace::Ppu,
+                    MemoryOperation::Write,
+                    vram_address,
+                    data,
+                );
+            }
+            VramTarget::Chr(addr) => self.dropped_write = Some(addr),
+            VramTarget::Nametable(addr) => {
+                let data = data.into();
+                self.vram[self.mirror_vram_addr(addr) as usize] = data;
+                self.log_access(
+                    AddressSpace::Ppu,
+                    MemoryOperation::Write,
+                    vram_address,
+                    data,
+                );
+            }
+            VramTarget::Palette(addr) => {
+                let data = data.into();
+                self.palette_table[palette_index(addr)] = data;
+                self.log_access(
+                    AddressSpace::Ppu,
+                    MemoryOperation::Write,
+                    vram_address,
+                    data,
+                );
             }
-            _ => panic!("Write on {:?} not supported", address),
         }
     }
 
@@ -272,25 +500,77 @@ impl PPU {
         }
     }
 
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.cycles as u16
+    }
+
+    // Level of the /NMI output, the CPU only reacts to it going from low to high
+    pub fn nmi_line(&self) -> bool {
+        self.ctrl.generate_vblank_nmi() && self.status.is_in_vblank()
+    }
+
+    // The bus drops a latched NMI when a $2002 read took the flag away right as it rose
+    pub fn take_nmi_suppressed(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_suppressed)
+    }
+
+    // True once the picture is complete, at the start of vblank
     pub fn tick(&mut self, cycles: u8) -> bool {
-        self.cycles += cycles as usize;
-        if self.cycles >= 341 {
-            self.cycles = self.cycles - 341;
-            self.scanline += 1;
+        let mut frame_complete = false;
+        for _ in 0..cycles {
+            frame_complete |= self.update_vblank();
+            self.render_dot();
+            self.next_dot();
+        }
+        frame_complete
+    }
 
-            if self.scanline == 241 {
-                if self.ctrl.generate_vblank_nmi() {
+    fn update_vblank(&mut self) -> bool {
+        if self.cycles != 1 {
+            return false;
+        }
+        match self.scanline {
+            VBLANK_LINE => {
+                if !std::mem::take(&mut self.suppress_vblank) {
                     self.status.set_vblank_status(true);
-                    todo!("Should trigger NMI interrupt")
                 }
+                true
             }
+            PRE_RENDER_LINE => {
+                self.status.reset_vblank_status();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn next_dot(&mut self) {
+        self.cycles += 1;
+        // With rendering on, the pre-render line of every other frame is a dot short
+        if self.scanline == 261
+            && self.cycles == 340
+            && self.frame % 2 == 1
+            && self.rendering_enabled()
+        {
+            self.cycles = 341;
+        }
+        if self.cycles >= 341 {
+            self.cycles = 0;
+            self.scanline += 1;
+            self.next_line_sprites();
 
-            if self.scanline >= 262 {
+            if self.scanline > PRE_RENDER_LINE {
                 self.scanline = 0;
-                self.status.reset_vblank_status();
-                return true;
+                self.frame += 1;
             }
         }
-        return false;
     }
 }
@@ -27,7 +27,7 @@ impl Control {
     }
 
     pub fn generate_vblank_nmi(&self) -> bool {
-        return self.contains(Self::GENERATE_NMI);
+        self.contains(Self::GENERATE_NMI)
     }
 
     pub fn update(&mut self, data: u8) {
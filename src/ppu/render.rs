@@ -1,9 +1,7 @@
-use sdl2::pixels::Palette;
-
 use super::{registers::Control, PPU};
+use crate::bus::cdl::ChrUsage;
 
 #[rustfmt::skip]
-
 pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
    (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), (0xA1, 0x00, 0x5E),
    (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00), (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00),
@@ -20,6 +18,7 @@ pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
 ];
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Frame {
     pub data: Vec<u8>,
 }
@@ -44,100 +43,421 @@ impl Frame {
             self.data[base + 2] = rgb.2;
         }
     }
+
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = y * 3 * Frame::WIDTH + x * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+}
+
+/*
+The PPU draws while it runs, one pixel per dot, the way the hardware does:
+
+- dots 1-256 of a visible line output a pixel each. Every 8 dots the background
+  fetches a nametable byte, an attribute byte and the two pattern bytes of the tile
+  after next, and loads them into the low half of 16 bit shift registers. Dots
+  321-336 fetch the first two tiles of the next line.
+- the fetches walk v (see loopy.rs): coarse X moves on after each tile, Y at dot
+  256, and X and Y are reloaded from t at dot 257 and on the pre-render line, so
+  scroll writes made during the frame show up from the next line on, and $2006
+  writes change where the fetches go right away.
+- at dot 257 OAM is searched for the (up to 8) sprites on the next line, their
+  patterns are fetched over dots 257-320 and they are output on the next line.
+
+Sprite overflow is set whenever a ninth sprite is found, without the hardware's
+buggy search. Color emphasis isn't applied.
+*/
+
+pub(super) const PRE_RENDER_LINE: u16 = 261;
+pub(super) const VBLANK_LINE: u16 = 241;
+const VISIBLE_LINES: u16 = 240;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Background {
+    // Latched by the fetches until the next reload
+    tile: u8,
+    attribute: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    // The current tile in the high byte, the next one in the low byte
+    shift_pattern_lo: u16,
+    shift_pattern_hi: u16,
+    shift_attribute_lo: u16,
+    shift_attribute_hi: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Sprite {
+    x: u8,
+    // Row of the sprite the line goes through
+    row: u8,
+    tile: u8,
+    attributes: u8,
+    zero: bool,
+    // Already flipped, the leftmost pixel is bit 7
+    pattern_lo: u8,
+    pattern_hi: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct SpriteLine {
+    sprites: [Sprite; 8],
+    count: usize,
 }
 
-pub fn render(ppu: &PPU, frame: &mut Frame) {
-    let bank = if !ppu.ctrl.contains(Control::BACKROUND_PATTERN_ADDR) {
-        0
-    } else {
-        0x1000
-    };
-
-    for i in 0..0x03c0 {
-        // just for now, lets use the first nametable
-        let tile = ppu.vram[i] as u16;
-        let tile_x = i % 32;
-        let tile_y = i / 32;
-        let tile = &ppu.chr_rom[(bank + tile * 16) as usize..=(bank + tile * 16 + 15) as usize];
-
-        for y in 0..=7 {
-            let mut upper = tile[y];
-            let mut lower = tile[y + 8];
-
-            for x in (0..=7).rev() {
-                let value = (1 & upper) << 1 | (1 & lower);
-                upper = upper >> 1;
-                lower = lower >> 1;
-                let rgb = match value {
-                    0 => SYSTEM_PALLETE[0x01],
-                    1 => SYSTEM_PALLETE[0x23],
-                    2 => SYSTEM_PALLETE[0x27],
-                    3 => SYSTEM_PALLETE[0x30],
-                    _ => panic!("can't be"),
-                };
-                frame.set_pixel(tile_x * 8 + x, tile_y * 8 + y, rgb)
+impl PPU {
+    pub(super) fn rendering_enabled(&self) -> bool {
+        self.mask.show_background() || self.mask.show_sprites()
+    }
+
+    // Runs the dot the PPU is at, advancing to the next one is up to tick
+    pub(super) fn render_dot(&mut self) {
+        let (line, dot) = (self.scanline, self.cycles as u16);
+        let visible = line < VISIBLE_LINES;
+        if !visible && line != PRE_RENDER_LINE {
+            return;
+        }
+        if line == PRE_RENDER_LINE && dot == 1 {
+            self.status.set_sprite_zero_hit(false);
+            self.status.set_sprite_overflow(false);
+        }
+        if !self.rendering_enabled() {
+            if visible && (1..=256).contains(&dot) {
+                let color = self.palette_table[0];
+                self.put_pixel(dot - 1, line, color);
+            }
+            return;
+        }
+
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.shift_background();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.reload_background();
+                    self.fetch_tile_number();
+                }
+                2 => self.fetch_attribute(),
+                4 => self.fetch_background_pattern(0),
+                6 => self.fetch_background_pattern(8),
+                7 => self.loopy.increment_coarse_x(),
+                _ => {}
             }
         }
+        match dot {
+            256 => self.loopy.increment_y(),
+            257 => {
+                self.reload_background();
+                self.loopy.copy_horizontal();
+                if visible {
+                    self.evaluate_sprites(line);
+                }
+            }
+            280..=304 if line == PRE_RENDER_LINE => self.loopy.copy_vertical(),
+            _ => {}
+        }
+        if (257..=320).contains(&dot) && (dot - 257) % 8 == 7 {
+            self.fetch_sprite_pattern(((dot - 257) / 8) as usize);
+        }
+
+        if visible && (1..=256).contains(&dot) {
+            self.output_pixel(dot - 1, line);
+        }
+    }
+
+    // Called when the PPU moves on to the next line
+    pub(super) fn next_line_sprites(&mut self) {
+        self.sprites = std::mem::take(&mut self.next_sprites);
+    }
+
+    fn read_nametable(&self, address: u16) -> u8 {
+        self.vram[self.mirror_vram_addr(0x2000 | address & 0x0fff) as usize]
     }
 
-    for i in (0..ppu.oam_data.len()).step_by(4).rev() {
-        let tile_idx = ppu.oam_data[i + 1] as u16;
-        let tile_x = ppu.oam_data[i + 3] as usize;
-        let tile_y = ppu.oam_data[i] as usize;
+    fn fetch_tile_number(&mut self) {
+        self.background.tile = self.read_nametable(self.loopy.v);
+    }
+
+    fn fetch_attribute(&mut self) {
+        let v = self.loopy.v;
+        let attribute =
+            self.read_nametable(0x03c0 | v & 0x0c00 | (v >> 4) & 0x38 | (v >> 2) & 0x07);
+        // Each byte covers 4x4 tiles, two bits per 2x2 quadrant
+        let shift = (v >> 4) & 0x04 | v & 0x02;
+        self.background.attribute = (attribute >> shift) & 0b11;
+    }
 
-        let flip_vertical = if ppu.oam_data[i + 2] >> 7 & 1 == 1 {
-            true
+    fn fetch_background_pattern(&mut self, plane: u16) {
+        let bank = if self.ctrl.contains(Control::BACKROUND_PATTERN_ADDR) {
+            0x1000
         } else {
-            false
+            0
         };
-        let flip_horizontal = if ppu.oam_data[i + 2] >> 6 & 1 == 1 {
-            true
+        let fine_y = self.loopy.v >> 12;
+        let address = bank + self.background.tile as u16 * 16 + plane + fine_y;
+        let pattern = self.fetch_pattern(address);
+        if plane == 0 {
+            self.background.pattern_lo = pattern;
         } else {
-            false
-        };
-        let pallette_idx = ppu.oam_data[i + 2] & 0b11;
-        let sprite_palette = sprite_palette(ppu, pallette_idx);
+            self.background.pattern_hi = pattern;
+        }
+    }
 
-        let bank: u16 = if ppu.ctrl.contains(Control::SPRITE_PATTERN_ADDR) {
-            0
+    pub(super) fn fetch_pattern(&mut self, address: u16) -> u8 {
+        if self.chr_usage.is_some() {
+            self.log_chr(address as usize, ChrUsage::RENDERED);
+        }
+        self.chr_rom.get(address as usize).copied().unwrap_or(0)
+    }
+
+    fn reload_background(&mut self) {
+        let background = &mut self.background;
+        background.shift_pattern_lo =
+            background.shift_pattern_lo & 0xff00 | background.pattern_lo as u16;
+        background.shift_pattern_hi =
+            background.shift_pattern_hi & 0xff00 | background.pattern_hi as u16;
+        // The attribute applies to the whole tile, so its bits are spread over 8 pixels
+        let spread = |bit: u8| if bit != 0 { 0xff } else { 0x00 };
+        background.shift_attribute_lo =
+            background.shift_attribute_lo & 0xff00 | spread(background.attribute & 0b01);
+        background.shift_attribute_hi =
+            background.shift_attribute_hi & 0xff00 | spread(background.attribute & 0b10);
+    }
+
+    fn shift_background(&mut self) {
+        let background = &mut self.background;
+        background.shift_pattern_lo <<= 1;
+        background.shift_pattern_hi <<= 1;
+        background.shift_attribute_lo <<= 1;
+        background.shift_attribute_hi <<= 1;
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl.contains(Control::SPRITE_SIZE) {
+            16
         } else {
-            0x1000
+            8
+        }
+    }
+
+    // OAM's Y is one less than the first line the sprite is on, so sprites found
+    // on this line are drawn from the next one down
+    fn evaluate_sprites(&mut self, line: u16) {
+        let height = self.sprite_height();
+        let mut next = SpriteLine::default();
+        for (index, entry) in self.oam_data.chunks_exact(4).enumerate() {
+            let row = line.wrapping_sub(entry[0] as u16);
+            if row >= height {
+                continue;
+            }
+            if next.count == 8 {
+                self.status.set_sprite_overflow(true);
+                break;
+            }
+            next.sprites[next.count] = Sprite {
+                x: entry[3],
+                row: row as u8,
+                tile: entry[1],
+                attributes: entry[2],
+                zero: index == 0,
+                pattern_lo: 0,
+                pattern_hi: 0,
+            };
+            next.count += 1;
+        }
+        self.next_sprites = next;
+    }
+
+    fn fetch_sprite_pattern(&mut self, slot: usize) {
+        if slot >= self.next_sprites.count {
+            return;
+        }
+        let sprite = self.next_sprites.sprites[slot];
+        let height = self.sprite_height();
+        let mut row = sprite.row as u16;
+        if sprite.attributes & 0x80 != 0 {
+            row = height - 1 - row;
+        }
+        let address = if height == 16 {
+            // Bit 0 of the tile number picks the pattern table, the top half comes first
+            let bank = (sprite.tile as u16 & 1) * 0x1000;
+            let tile = (sprite.tile & 0xfe) as u16 + row / 8;
+            bank + tile * 16 + row % 8
+        } else {
+            let bank = if self.ctrl.contains(Control::SPRITE_PATTERN_ADDR) {
+                0x1000
+            } else {
+                0
+            };
+            bank + sprite.tile as u16 * 16 + row
         };
+        let (mut lo, mut hi) = (self.fetch_pattern(address), self.fetch_pattern(address + 8));
+        if sprite.attributes & 0x40 != 0 {
+            lo = lo.reverse_bits();
+            hi = hi.reverse_bits();
+        }
+        let sprite = &mut self.next_sprites.sprites[slot];
+        sprite.pattern_lo = lo;
+        sprite.pattern_hi = hi;
+    }
+
+    fn background_pixel(&self) -> (u8, u8) {
+        let bit = 0x8000 >> self.loopy.x;
+        let background = &self.background;
+        let pick = |shift: u16, value: u8| if shift & bit != 0 { value } else { 0 };
+        let pixel = pick(background.shift_pattern_lo, 1) | pick(background.shift_pattern_hi, 2);
+        let palette =
+            pick(background.shift_attribute_lo, 1) | pick(background.shift_attribute_hi, 2);
+        (pixel, palette)
+    }
+
+    // The first opaque sprite at x: its pixel, its attributes and whether it is sprite 0
+    fn sprite_pixel(&self, x: u16) -> Option<(u8, u8, bool)> {
+        self.sprites.sprites[..self.sprites.count]
+            .iter()
+            .find_map(|sprite| {
+                let column = x.wrapping_sub(sprite.x as u16);
+                if column >= 8 {
+                    return None;
+                }
+                let bit = 0x80 >> column;
+                let pixel = (sprite.pattern_lo & bit != 0) as u8
+                    | ((sprite.pattern_hi & bit != 0) as u8) << 1;
+                (pixel != 0).then_some((pixel, sprite.attributes, sprite.zero))
+            })
+    }
+
+    fn output_pixel(&mut self, x: u16, line: u16) {
+        let (mut background, palette) = self.background_pixel();
+        if !self.mask.show_background() || (x < 8 && !self.mask.leftmost_8pxl_background()) {
+            background = 0;
+        }
+        let mut sprite = self.sprite_pixel(x);
+        if !self.mask.show_sprites() || (x < 8 && !self.mask.leftmost_8pxl_sprite()) {
+            sprite = None;
+        }
 
-        let tile =
-            &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
-
-        for y in 0..=7 {
-            let mut upper = tile[y];
-            let mut lower = tile[y + 8];
-            'ololo: for x in (0..=7).rev() {
-                let value = (1 & lower) << 1 | (1 & upper);
-                upper = upper >> 1;
-                lower = lower >> 1;
-                let rgb = match value {
-                    0 => continue 'ololo, // skip coloring the pixel
-                    1 => SYSTEM_PALLETE[sprite_palette[1] as usize],
-                    2 => SYSTEM_PALLETE[sprite_palette[2] as usize],
-                    3 => SYSTEM_PALLETE[sprite_palette[3] as usize],
-                    _ => panic!("can't be"),
-                };
-                match (flip_horizontal, flip_vertical) {
-                    (false, false) => frame.set_pixel(tile_x + x, tile_y + y, rgb),
-                    (true, false) => frame.set_pixel(tile_x + 7 - x, tile_y + y, rgb),
-                    (false, true) => frame.set_pixel(tile_x + x, tile_y + 7 - y, rgb),
-                    (true, true) => frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y, rgb),
+        let color = match (background, sprite) {
+            (0, None) => self.palette_table[0],
+            (0, Some((pixel, attributes, _))) => self.sprite_color(pixel, attributes),
+            (_, None) => self.palette_table[(palette * 4 + background) as usize],
+            (_, Some((pixel, attributes, zero))) => {
+                if zero && x != 255 {
+                    self.status.set_sprite_zero_hit(true);
+                }
+                if attributes & 0x20 != 0 {
+                    self.palette_table[(palette * 4 + background) as usize]
+                } else {
+                    self.sprite_color(pixel, attributes)
                 }
             }
+        };
+        self.put_pixel(x, line, color);
+    }
+
+    fn sprite_color(&self, pixel: u8, attributes: u8) -> u8 {
+        self.palette_table[(0x10 + (attributes & 0b11) * 4 + pixel) as usize]
+    }
+
+    fn put_pixel(&mut self, x: u16, line: u16, color: u8) {
+        let mut color = color & 0x3f;
+        if self.mask.is_grayscale() {
+            color &= 0x30;
         }
+        self.frame_buffer
+            .set_pixel(x as usize, line as usize, SYSTEM_PALLETE[color as usize]);
     }
 }
 
-fn sprite_palette(ppu: &PPU, pallete_idx: u8) -> [u8; 4] {
-    let start = 0x11 + (pallete_idx * 4) as usize;
-    [
-        0,
-        ppu.palette_table[start],
-        ppu.palette_table[start + 1],
-        ppu.palette_table[start + 2],
-    ]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ppu::PPUValue;
+    use crate::rom::Mirroring;
+
+    // Tile 1 is solid color 1, tile 2 solid color 3
+    fn ppu() -> PPU {
+        let mut chr = vec![0; 0x2000];
+        chr[0x10..0x18].fill(0xff);
+        chr[0x20..0x30].fill(0xff);
+        let mut ppu = PPU::new(chr, Mirroring::Horizontal);
+        ppu.palette_table[0] = 0x0f;
+        ppu.palette_table[1] = 0x01;
+        ppu.palette_table[0x13] = 0x16;
+        // Show the background and sprites, left column included
+        ppu.write_register(0x2001, PPUValue::Byte(0b0001_1110));
+        ppu
+    }
+
+    fn run_to(ppu: &mut PPU, scanline: u16) {
+        while ppu.scanline != scanline {
+            ppu.tick(1);
+        }
+    }
+
+    fn color(ppu: &PPU, index: usize) -> (u8, u8, u8) {
+        SYSTEM_PALLETE[ppu.palette_table[index] as usize]
+    }
+
+    #[test]
+    fn test_scroll_split() {
+        let mut ppu = ppu();
+        for row in 0..30 {
+            ppu.vram[row * 32] = 1;
+        }
+        // The pre-render line sets up the first frame
+        run_to(&mut ppu, 261);
+        run_to(&mut ppu, 100);
+        ppu.write_register(0x2005, PPUValue::Byte(8));
+        ppu.write_register(0x2005, PPUValue::Byte(0));
+        run_to(&mut ppu, 240);
+
+        let frame = ppu.frame_buffer();
+        assert_eq!(frame.pixel(0, 50), color(&ppu, 1));
+        assert_eq!(frame.pixel(8, 50), color(&ppu, 0));
+        // One tile further along, the second nametable (a mirror of the first) comes in on the right
+        assert_eq!(frame.pixel(0, 150), color(&ppu, 0));
+        assert_eq!(frame.pixel(248, 150), color(&ppu, 1));
+        assert_eq!(frame.pixel(247, 150), color(&ppu, 0));
+    }
+
+    #[test]
+    fn test_address_split() {
+        let mut ppu = ppu();
+        ppu.vram[20 * 32] = 1;
+        run_to(&mut ppu, 261);
+        run_to(&mut ppu, 100);
+        // $2006 goes straight into v, so the fetches jump to row 20 from here on
+        ppu.write_register(0x2006, PPUValue::Byte(0x02));
+        ppu.write_register(0x2006, PPUValue::Byte(0x80));
+        run_to(&mut ppu, 240);
+
+        let frame = ppu.frame_buffer();
+        assert_eq!(frame.pixel(0, 99), color(&ppu, 0));
+        assert_eq!(frame.pixel(0, 101), color(&ppu, 1));
+        assert_eq!(frame.pixel(0, 107), color(&ppu, 1));
+        assert_eq!(frame.pixel(0, 108), color(&ppu, 0));
+        assert_eq!(frame.pixel(0, 160), color(&ppu, 0));
+    }
+
+    #[test]
+    fn test_sprites() {
+        let mut ppu = ppu();
+        ppu.vram[6 * 32 + 2] = 1;
+        // Sprite 0 over the background tile, sprite 1 flipped and behind it
+        ppu.oam_data[..8].copy_from_slice(&[49, 2, 0x00, 20, 49, 2, 0x20, 16]);
+        run_to(&mut ppu, 261);
+        run_to(&mut ppu, 240);
+
+        let frame = ppu.frame_buffer();
+        assert!(ppu
+            .status
+            .contains(super::super::registers::Status::SPRITE_ZERO_HIT));
+        assert_eq!(frame.pixel(20, 50), color(&ppu, 0x13));
+        assert_eq!(frame.pixel(27, 57), color(&ppu, 0x13));
+        assert_eq!(frame.pixel(28, 50), color(&ppu, 0));
+        // Behind the background where there is some, over the backdrop elsewhere
+        assert_eq!(frame.pixel(16, 50), color(&ppu, 1));
+        assert_eq!(frame.pixel(16, 56), color(&ppu, 0x13));
+        assert_eq!(frame.pixel(16, 47), color(&ppu, 0));
+    }
 }
@@ -54,6 +54,10 @@ impl Rom {
         })
     }
 
+    pub fn mapper(&self) -> u8 {
+        self.mapper
+    }
+
     pub fn from_path(rom_path: String) -> Result<Self, String> {
         let mut buffer: Vec<u8> = Vec::new();
         File::open(rom_path)
@@ -0,0 +1,298 @@
+/*
+Runs the SingleStepTests (https://github.com/SingleStepTests/ProcessorTests) JSON
+files against the CPU. Every test is one instruction: the initial registers and
+RAM, the expected final registers and RAM, and every bus cycle in between.
+
+The files are not part of the repo, point SINGLE_STEP_TESTS at a directory holding
+the 00.json ... ff.json files (nes6502/v1 for the default 2A03, 6502/v1 with
+SINGLE_STEP_VARIANT=6502 or wdc65c02 with SINGLE_STEP_VARIANT=65c02) and run
+
+    cargo test --release --test single_step -- --ignored --nocapture
+
+to see the per opcode table. tests/single_step_fixtures holds a few hand checked
+tests in the same format that always run, so the runner itself is covered.
+*/
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use nes::{
+    bus::Bus,
+    cpu::{
+        instructions::InstructionType, processor_status::ProcessorStatus, variant::CpuVariant, CPU,
+    },
+};
+use serde_json::Value;
+
+// B and bit 5 only exist on the stack, the register itself can hold anything there
+const UNUSED_FLAGS: u8 = 0b0011_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+// Plain 64 KiB of RAM that remembers every access the CPU makes
+struct RecordingBus {
+    memory: Vec<u8>,
+    accesses: Vec<(u16, u8, Access)>,
+}
+
+impl Bus for RecordingBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.memory[address as usize];
+        self.accesses.push((address, data, Access::Read));
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+        self.accesses.push((address, data, Access::Write));
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn tick(&mut self, _cycles: u8) {}
+}
+
+#[derive(Default)]
+struct OpcodeResult {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+    first_failure: Option<String>,
+}
+
+fn field(state: &Value, name: &str) -> u64 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing field {}", name))
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("missing ram")
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}
+
+fn cycles(test: &Value) -> Vec<(u16, u8, Access)> {
+    test["cycles"]
+        .as_array()
+        .expect("missing cycles")
+        .iter()
+        .map(|cycle| {
+            let access = match cycle[2].as_str().unwrap() {
+                "read" => Access::Read,
+                "write" => Access::Write,
+                other => panic!("unknown bus access {}", other),
+            };
+            (
+                cycle[0].as_u64().unwrap() as u16,
+                cycle[1].as_u64().unwrap() as u8,
+                access,
+            )
+        })
+        .collect()
+}
+
+fn run_test(cpu: &mut CPU<RecordingBus>, variant: CpuVariant, test: &Value) -> Result<(), String> {
+    let initial = &test["initial"];
+    let expected = &test["final"];
+
+    let initial_ram = ram(initial);
+    for &(address, data) in &initial_ram {
+        cpu.bus.memory[address as usize] = data;
+    }
+    cpu.bus.accesses.clear();
+    cpu.program_counter = field(initial, "pc") as u16;
+    cpu.stack_pointer = field(initial, "s") as u8;
+    cpu.a = field(initial, "a") as u8;
+    cpu.x = field(initial, "x") as u8;
+    cpu.y = field(initial, "y") as u8;
+    cpu.processor_status = ProcessorStatus::from_bits_truncate(field(initial, "p") as u8);
+    cpu.variant = variant;
+
+    cpu.step();
+
+    let mut errors = vec![];
+    let registers = [
+        ("pc", cpu.program_counter as u64),
+        ("s", cpu.stack_pointer as u64),
+        ("a", cpu.a as u64),
+        ("x", cpu.x as u64),
+        ("y", cpu.y as u64),
+    ];
+    for (name, actual) in registers {
+        if actual != field(expected, name) {
+            errors.push(format!(
+                "{}: {:#x} expected {:#x}",
+                name,
+                actual,
+                field(expected, name)
+            ));
+        }
+    }
+    let p = cpu.processor_status.bits() | UNUSED_FLAGS;
+    if p != field(expected, "p") as u8 | UNUSED_FLAGS {
+        errors.push(format!(
+            "p: {:#010b} expected {:#010b}",
+            p,
+            field(expected, "p")
+        ));
+    }
+    for (address, data) in ram(expected) {
+        let actual = cpu.bus.memory[address as usize];
+        if actual != data {
+            errors.push(format!(
+                "ram[{:#06x}]: {:#04x} expected {:#04x}",
+                address, actual, data
+            ));
+        }
+    }
+    let expected_cycles = cycles(test);
+    if cpu.bus.accesses != expected_cycles {
+        errors.push(format!(
+            "bus: {:x?} expected {:x?}",
+            cpu.bus.accesses, expected_cycles
+        ));
+    }
+
+    // Leave the memory zeroed for the next test
+    for &(address, _, _) in &cpu.bus.accesses {
+        cpu.bus.memory[address as usize] = 0;
+    }
+    for (address, _) in initial_ram {
+        cpu.bus.memory[address as usize] = 0;
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: {}",
+            test["name"].as_str().unwrap_or("?"),
+            errors.join(", ")
+        ))
+    }
+}
+
+fn run_opcode(
+    cpu: &mut CPU<RecordingBus>,
+    variant: CpuVariant,
+    path: &Path,
+    op_code: u8,
+) -> OpcodeResult {
+    let mut result = OpcodeResult::default();
+    let file = fs::read_to_string(path).expect("could not read test file");
+    let tests: Value = serde_json::from_str(&file).expect("could not parse test file");
+    let tests = tests.as_array().expect("test file is not a list of tests");
+
+    // A halted CPU stays on the bus forever, there is nothing to compare
+    if variant.instruction_set()[op_code as usize].instruction_type == InstructionType::KIL {
+        result.skipped = tests.len();
+        return result;
+    }
+
+    for test in tests {
+        match run_test(cpu, variant, test) {
+            Ok(()) => result.passed += 1,
+            Err(error) => {
+                result.failed += 1;
+                result.first_failure.get_or_insert(error);
+            }
+        }
+    }
+    result
+}
+
+// Runs every NN.json in dir, returns how many tests passed and the opcodes that failed
+fn run_dir(dir: &Path, variant: CpuVariant) -> (usize, Vec<u8>) {
+    let bus = RecordingBus {
+        memory: vec![0; 0x10000],
+        accesses: vec![],
+    };
+    let mut cpu = CPU::new_with_state(bus, 0, 0, 0, 0, 0, ProcessorStatus::default(), 0);
+
+    let mut passed = 0;
+    let mut failed_opcodes = vec![];
+    println!();
+    println!(
+        "{:<6} {:<4} {:>7} {:>7} {:>7}",
+        "opcode", "name", "passed", "failed", "skipped"
+    );
+    for op_code in 0..=255u8 {
+        let path = dir.join(format!("{:02x}.json", op_code));
+        if !path.exists() {
+            continue;
+        }
+        let instruction = &variant.instruction_set()[op_code as usize];
+        let result = run_opcode(&mut cpu, variant, &path, op_code);
+        println!(
+            "{:#04x}   {:<4} {:>7} {:>7} {:>7}",
+            op_code,
+            format!("{:?}", instruction.instruction_type),
+            result.passed,
+            result.failed,
+            result.skipped
+        );
+        passed += result.passed;
+        if let Some(failure) = result.first_failure {
+            println!("       first failure: {}", failure);
+            failed_opcodes.push(op_code);
+        }
+    }
+    (passed, failed_opcodes)
+}
+
+#[test]
+fn test_single_step_fixtures() {
+    let (passed, failed_opcodes) = run_dir(
+        Path::new("./tests/single_step_fixtures"),
+        CpuVariant::Ricoh2A03,
+    );
+    assert_eq!(passed, 5);
+    assert!(
+        failed_opcodes.is_empty(),
+        "opcodes with failing tests: {:02x?}",
+        failed_opcodes
+    );
+}
+
+// The full suite, see the top of the file
+#[test]
+#[ignore]
+fn test_single_step() {
+    let dir = PathBuf::from(
+        env::var("SINGLE_STEP_TESTS").unwrap_or_else(|_| "./tests/single_step".to_owned()),
+    );
+    assert!(
+        dir.is_dir(),
+        "{} not found, set SINGLE_STEP_TESTS to the test files",
+        dir.display()
+    );
+    let variant = match env::var("SINGLE_STEP_VARIANT").as_deref() {
+        Ok("6502") => CpuVariant::Nmos6502,
+        Ok("65c02") => CpuVariant::Cmos65C02,
+        _ => CpuVariant::Ricoh2A03,
+    };
+
+    let (passed, failed_opcodes) = run_dir(&dir, variant);
+    assert!(passed > 0, "no tests found in {}", dir.display());
+    assert!(
+        failed_opcodes.is_empty(),
+        "opcodes with failing tests: {:02x?}",
+        failed_opcodes
+    );
+}
@@ -1,15 +1,15 @@
 use std::{
     collections::VecDeque,
-    fs::File,
+    fs::{self, File},
     io::{BufRead, BufReader},
-    ops::Deref,
 };
 
 use nes::{
-    bus::MemoryBus,
+    bus::{Bus, MemoryBus},
     cpu::{
         instructions::{get_instruction_from_opcode, Instruction},
         processor_status::ProcessorStatus,
+        trace::{first_divergence, TraceLogger, TraceTrigger},
         CPU,
     },
     rom::Rom,
@@ -39,37 +39,32 @@ impl CPURecorder {
             // Extract opcode and operands
             let opcode_str = instruction_parts[1];
 
-            expected_instruction.push_back(
-                get_instruction_from_opcode(
-                    u8::from_str_radix(opcode_str, 16).expect("could not convert str to u8")
-                        as usize,
-                )
-                .deref()
-                .clone(),
-            );
+            expected_instruction.push_back(*get_instruction_from_opcode(
+                u8::from_str_radix(opcode_str, 16).expect("could not convert str to u8") as usize,
+            ));
 
             let state_parts: Vec<&str> = parts[1].split_whitespace().collect();
 
             let program_counter = u16::from_str_radix(instruction_parts[0], 16).unwrap();
-            let a = u8::from_str_radix(&state_parts[0], 16).unwrap();
+            let a = u8::from_str_radix(state_parts[0], 16).unwrap();
             let x = u8::from_str_radix(&state_parts[1][2..4], 16).unwrap();
             let y = u8::from_str_radix(&state_parts[2][2..4], 16).unwrap();
             let processor_status = ProcessorStatus::from_bits_truncate(
                 u8::from_str_radix(&state_parts[3][2..4], 16).unwrap(),
             );
             let stack_pointer = u8::from_str_radix(&state_parts[4][3..5], 16).unwrap();
-            let cycle = u64::from_str_radix(&state_parts.last().unwrap()[4..], 10).unwrap();
+            let cycle = state_parts.last().unwrap()[4..].parse::<u64>().unwrap();
 
-            expected_cpu_state.push_back(CPU {
+            expected_cpu_state.push_back(CPU::new_with_state(
+                test_rom(),
                 program_counter,
+                stack_pointer,
                 a,
                 x,
                 y,
                 processor_status,
-                stack_pointer,
-                bus: test_rom(),
                 cycle,
-            })
+            ))
         }
         // State has to be one ahead
         expected_cpu_state.pop_front().unwrap();
@@ -117,6 +112,10 @@ impl CPURecorder {
         self.count += 1;
     }
 
+    fn finished(&self) -> bool {
+        self.expected_cpu_state.is_empty()
+    }
+
     fn check_instruction(&mut self, cpu: &CPU, instruction: &Instruction) {
         let expected_instruction = self
             .expected_instruction
@@ -133,8 +132,8 @@ impl CPURecorder {
 fn test_rom() -> MemoryBus {
     let test_rom =
         Rom::from_path("./tests/nestest.nes".to_owned()).expect("could not open the rom");
-    let bus = MemoryBus::new(test_rom);
-    bus
+
+    MemoryBus::new(test_rom)
 }
 
 #[test]
@@ -154,7 +153,42 @@ fn test_cpu() {
     let mut cpu_recorder = CPURecorder::new_from_nes_log("./tests/nestest.log");
 
     cpu.start_with_callback(|cpu, instruction| {
-        cpu_recorder.check_state(&cpu);
-        cpu_recorder.check_instruction(&cpu, instruction);
+        cpu_recorder.check_instruction(cpu, instruction);
+        // The last log line has no following state to compare against
+        if cpu_recorder.finished() {
+            cpu.halted = true;
+            return;
+        }
+        cpu_recorder.check_state(cpu);
     });
+
+    assert!(cpu_recorder.expected_instruction.is_empty());
+    assert_eq!(cpu_recorder.count, 8990);
+}
+
+#[test]
+fn test_trace_matches_nestest_log() {
+    let mut cpu = CPU::new_with_state(
+        test_rom(),
+        0xC000,
+        0xFD,
+        0,
+        0,
+        0,
+        ProcessorStatus::from_bits_truncate(0x24),
+        7,
+    );
+    // The reset sequence already ran the PPU for 7 CPU cycles
+    cpu.bus.tick(7);
+
+    let reference = fs::read_to_string("./tests/nestest.log").expect("nes log not found");
+    let mut logger = TraceLogger::new(vec![], TraceTrigger::Immediately, TraceTrigger::Never);
+    for _ in reference.lines() {
+        logger.log(&cpu).unwrap();
+        cpu.step();
+    }
+
+    let trace = logger.into_inner().unwrap();
+    let divergence = first_divergence(trace.as_slice(), reference.as_bytes()).unwrap();
+    assert_eq!(divergence, None);
 }